@@ -0,0 +1,934 @@
+//! Sequential versus parallel benchmark harness.
+//!
+//! A workload is a number of independent units of work. The harness runs all
+//! units on the calling thread, then splits them across N scoped threads, and
+//! compares the two timings: speedup, parallel efficiency and the serial
+//! fraction implied by Amdahl's law (the Karp-Flatt metric).
+//!
+//! The channel benchmark measures something else: messages per second from
+//! one producer to one consumer, for the per-message `std::sync::mpsc`
+//! baseline, the [`spsc`] ring buffer, and the batched operations of [`mpmc`]
+//! at several batch sizes. The false sharing benchmark times threads that
+//! each increment their own counter, with the counters packed together and
+//! then spread over separate cache lines.
+//!
+//! Every kind of report implements [`Report`], and [`to_table`], [`to_json`]
+//! and [`to_csv`] render any of them, the reports of [`crate::counter`] too.
+
+use std::fmt::Write as _;
+use std::hint::black_box;
+use std::mem;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cache_padded::CachePadded;
+use crate::channel::{mpmc, spsc};
+
+/// How a benchmark is repeated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchConfig {
+    /// Units of work in one run
+    pub units: usize,
+    /// Untimed runs executed before measuring
+    pub warmup: usize,
+    /// Timed runs used for the statistics
+    pub repetitions: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig {
+            units: 16,
+            warmup: 1,
+            repetitions: 5,
+        }
+    }
+}
+
+/// Summary of the timings of repeated runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub min: Duration,
+    pub median: Duration,
+    pub mean: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl Stats {
+    /// Computes the statistics of a non-empty set of samples
+    pub fn from_samples(samples: &[Duration]) -> Stats {
+        assert!(!samples.is_empty(), "at least one sample is needed");
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let len = sorted.len();
+        let median = if len.is_multiple_of(2) {
+            (sorted[len / 2 - 1] + sorted[len / 2]) / 2
+        } else {
+            sorted[len / 2]
+        };
+        // Nearest-rank percentile
+        let p95_rank = (len * 95).div_ceil(100).max(1);
+        Stats {
+            min: sorted[0],
+            median,
+            mean: sorted.iter().sum::<Duration>() / len as u32,
+            p95: sorted[p95_rank - 1],
+            max: sorted[len - 1],
+        }
+    }
+}
+
+/// Result of comparing one workload run sequentially and on `threads` threads
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    pub name: String,
+    pub threads: usize,
+    pub units: usize,
+    pub repetitions: usize,
+    pub sequential: Stats,
+    pub parallel: Stats,
+}
+
+impl BenchReport {
+    /// Sequential median time divided by parallel median time
+    pub fn speedup(&self) -> f64 {
+        self.sequential.median.as_secs_f64() / self.parallel.median.as_secs_f64()
+    }
+
+    /// Speedup per thread, 1.0 meaning perfect scaling
+    pub fn efficiency(&self) -> f64 {
+        self.speedup() / self.threads as f64
+    }
+
+    /// Serial fraction of the workload implied by the measured speedup.
+    ///
+    /// This is the Karp-Flatt metric `(1/S - 1/p) / (1 - 1/p)`; it is only
+    /// defined for more than one thread.
+    pub fn serial_fraction(&self) -> Option<f64> {
+        if self.threads < 2 {
+            return None;
+        }
+        let p = self.threads as f64;
+        Some((1.0 / self.speedup() - 1.0 / p) / (1.0 - 1.0 / p))
+    }
+
+    /// Upper bound on the speedup with unlimited threads according to Amdahl's law
+    pub fn amdahl_limit(&self) -> Option<f64> {
+        self.serial_fraction()
+            .filter(|fraction| *fraction > 0.0)
+            .map(|fraction| 1.0 / fraction)
+    }
+}
+
+/// Times `units` units of `workload`, sequentially and split across `threads` threads.
+///
+/// The workload receives the range of units it has to process; the parallel
+/// run hands each thread a contiguous chunk.
+pub fn compare<W>(name: &str, threads: usize, config: &BenchConfig, workload: W) -> BenchReport
+where
+    W: Fn(Range<usize>) + Sync,
+{
+    assert!(threads > 0, "at least one thread is needed");
+    assert!(config.repetitions > 0, "at least one repetition is needed");
+    let sequential = measure(config, || workload(0..config.units));
+    let parallel = measure(config, || run_parallel(threads, config.units, &workload));
+    BenchReport {
+        name: name.to_string(),
+        threads,
+        units: config.units,
+        repetitions: config.repetitions,
+        sequential,
+        parallel,
+    }
+}
+
+/// Runs `run` for the warm-up rounds, then times each repetition
+pub(crate) fn measure(config: &BenchConfig, mut run: impl FnMut()) -> Stats {
+    for _ in 0..config.warmup {
+        run();
+    }
+    let samples: Vec<Duration> = (0..config.repetitions)
+        .map(|_| {
+            let started = Instant::now();
+            run();
+            started.elapsed()
+        })
+        .collect();
+    Stats::from_samples(&samples)
+}
+
+/// Splits `0..units` into `threads` contiguous chunks and processes them concurrently
+fn run_parallel<W>(threads: usize, units: usize, workload: &W)
+where
+    W: Fn(Range<usize>) + Sync,
+{
+    thread::scope(|scope| {
+        for index in 0..threads {
+            let start = units * index / threads;
+            let end = units * (index + 1) / threads;
+            if start < end {
+                scope.spawn(move || workload(start..end));
+            }
+        }
+    });
+}
+
+/// Built-in workloads available from the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    /// Every unit sleeps, like `calculate_counter` does between its steps
+    Sleep(Duration),
+    /// Every unit spins the CPU for the given number of rounds
+    Spin(u64),
+}
+
+impl Workload {
+    /// Name used in reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            Workload::Sleep(_) => "sleep",
+            Workload::Spin(_) => "spin",
+        }
+    }
+
+    /// Processes one unit of work
+    pub fn unit(&self) {
+        match *self {
+            Workload::Sleep(delay) => thread::sleep(delay),
+            Workload::Spin(rounds) => {
+                // A xorshift generator the optimizer cannot see through
+                let mut state = black_box(0x2545_f491_4f6c_dd1d_u64);
+                for _ in 0..rounds {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                }
+                black_box(state);
+            }
+        }
+    }
+
+    /// Compares this workload sequentially and on `threads` threads
+    pub fn compare(&self, threads: usize, config: &BenchConfig) -> BenchReport {
+        compare(self.name(), threads, config, |units| {
+            for _ in units {
+                self.unit();
+            }
+        })
+    }
+}
+
+/// A column of the text table rendering of a [`Report`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    pub heading: &'static str,
+    /// Minimum width in characters
+    pub width: usize,
+    /// Whether cells are padded on the right instead of the left
+    pub left: bool,
+}
+
+impl Column {
+    /// A left-aligned column, for names
+    pub const fn left(heading: &'static str, width: usize) -> Column {
+        Column {
+            heading,
+            width,
+            left: true,
+        }
+    }
+
+    /// A right-aligned column, for numbers
+    pub const fn right(heading: &'static str, width: usize) -> Column {
+        Column {
+            heading,
+            width,
+            left: false,
+        }
+    }
+}
+
+/// The value of one field of a [`Report`] in JSON and CSV
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    /// A string, escaped as each format requires
+    Text(&'a str),
+    Count(u64),
+    /// Printed with six decimals; missing or non-finite values are `null` in
+    /// JSON and an empty cell in CSV
+    Float(Option<f64>),
+    /// Timings in nanoseconds: an object with every statistic in JSON, the
+    /// median and p95 columns in CSV
+    Stats(Stats),
+}
+
+impl<'a> From<&'a str> for Value<'a> {
+    fn from(text: &'a str) -> Self {
+        Value::Text(text)
+    }
+}
+
+impl From<usize> for Value<'_> {
+    fn from(count: usize) -> Self {
+        Value::Count(count as u64)
+    }
+}
+
+impl From<u64> for Value<'_> {
+    fn from(count: u64) -> Self {
+        Value::Count(count)
+    }
+}
+
+impl From<f64> for Value<'_> {
+    fn from(value: f64) -> Self {
+        Value::Float(Some(value))
+    }
+}
+
+impl From<Option<f64>> for Value<'_> {
+    fn from(value: Option<f64>) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<Stats> for Value<'_> {
+    fn from(stats: Stats) -> Self {
+        Value::Stats(stats)
+    }
+}
+
+/// A benchmark result that [`to_table`], [`to_json`] and [`to_csv`] render.
+///
+/// Both methods get every report being rendered, for the fields that compare
+/// a report with another one.
+pub trait Report: Sized {
+    /// The columns of the text table
+    const COLUMNS: &'static [Column];
+
+    /// The text of every table column
+    fn cells(&self, reports: &[Self]) -> Vec<String>;
+
+    /// The named fields of the JSON object and the CSV row
+    fn fields(&self, reports: &[Self]) -> Vec<(&'static str, Value<'_>)>;
+}
+
+/// Renders reports as an aligned text table
+pub fn to_table<R: Report>(reports: &[R]) -> String {
+    let mut out = String::new();
+    let headings = R::COLUMNS.iter().map(|column| column.heading.to_string());
+    table_row(&mut out, R::COLUMNS, headings.collect());
+    for report in reports {
+        table_row(&mut out, R::COLUMNS, report.cells(reports));
+    }
+    out
+}
+
+fn table_row(out: &mut String, columns: &[Column], cells: Vec<String>) {
+    let cells: Vec<String> = columns
+        .iter()
+        .zip(cells)
+        .map(|(column, cell)| {
+            let width = column.width;
+            if column.left {
+                format!("{:<width$}", cell)
+            } else {
+                format!("{:>width$}", cell)
+            }
+        })
+        .collect();
+    writeln!(out, "{}", cells.join(" ")).unwrap();
+}
+
+/// Renders reports as a JSON array of objects, durations in nanoseconds
+pub fn to_json<R: Report>(reports: &[R]) -> String {
+    let entries: Vec<String> = reports
+        .iter()
+        .map(|report| {
+            let fields: Vec<String> = report
+                .fields(reports)
+                .into_iter()
+                .map(|(name, value)| format!("{}:{}", json_string(name), json_value(value)))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        })
+        .collect();
+    format!("[{}]", entries.join(","))
+}
+
+fn json_value(value: Value<'_>) -> String {
+    match value {
+        Value::Text(text) => json_string(text),
+        Value::Count(count) => count.to_string(),
+        Value::Float(value) => json_float(value),
+        Value::Stats(stats) => format!(
+            "{{\"min_ns\":{},\"median_ns\":{},\"mean_ns\":{},\"p95_ns\":{},\"max_ns\":{}}}",
+            stats.min.as_nanos(),
+            stats.median.as_nanos(),
+            stats.mean.as_nanos(),
+            stats.p95.as_nanos(),
+            stats.max.as_nanos()
+        ),
+    }
+}
+
+/// A JSON string literal
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A JSON number with six decimals, `null` if missing or not finite
+fn json_float(value: Option<f64>) -> String {
+    match value {
+        Some(value) if value.is_finite() => format!("{:.6}", value),
+        _ => "null".to_string(),
+    }
+}
+
+/// Renders reports as CSV with a header row, durations in nanoseconds.
+///
+/// The header comes from the fields of the first report, so no reports
+/// render as nothing at all.
+pub fn to_csv<R: Report>(reports: &[R]) -> String {
+    let mut out = String::new();
+    let Some(first) = reports.first() else {
+        return out;
+    };
+    let header: Vec<String> = first
+        .fields(reports)
+        .into_iter()
+        .flat_map(|(name, value)| match value {
+            Value::Stats(_) => vec![format!("{}_median_ns", name), format!("{}_p95_ns", name)],
+            _ => vec![name.to_string()],
+        })
+        .collect();
+    writeln!(out, "{}", header.join(",")).unwrap();
+    for report in reports {
+        let cells: Vec<String> = report
+            .fields(reports)
+            .into_iter()
+            .flat_map(|(_, value)| match value {
+                Value::Text(text) => vec![csv_string(text)],
+                Value::Count(count) => vec![count.to_string()],
+                Value::Float(Some(value)) if value.is_finite() => vec![format!("{:.6}", value)],
+                Value::Float(_) => vec![String::new()],
+                Value::Stats(stats) => vec![
+                    stats.median.as_nanos().to_string(),
+                    stats.p95.as_nanos().to_string(),
+                ],
+            })
+            .collect();
+        writeln!(out, "{}", cells.join(",")).unwrap();
+    }
+    out
+}
+
+/// A CSV cell, quoted if it holds a separator, a quote or a line break
+fn csv_string(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+impl Report for BenchReport {
+    const COLUMNS: &'static [Column] = &[
+        Column::left("workload", 10),
+        Column::right("threads", 7),
+        Column::right("units", 6),
+        Column::right("seq median", 12),
+        Column::right("seq p95", 12),
+        Column::right("par median", 12),
+        Column::right("par p95", 12),
+        Column::right("speedup", 8),
+        Column::right("efficiency", 10),
+        Column::right("serial", 8),
+    ];
+
+    fn cells(&self, _: &[Self]) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.threads.to_string(),
+            self.units.to_string(),
+            format!("{:.2?}", self.sequential.median),
+            format!("{:.2?}", self.sequential.p95),
+            format!("{:.2?}", self.parallel.median),
+            format!("{:.2?}", self.parallel.p95),
+            format!("{:.2}", self.speedup()),
+            format!("{:.1}%", self.efficiency() * 100.0),
+            self.serial_fraction()
+                .map_or("-".to_string(), |fraction| format!("{:.3}", fraction)),
+        ]
+    }
+
+    fn fields(&self, _: &[Self]) -> Vec<(&'static str, Value<'_>)> {
+        vec![
+            ("workload", self.name.as_str().into()),
+            ("threads", self.threads.into()),
+            ("units", self.units.into()),
+            ("repetitions", self.repetitions.into()),
+            ("sequential", self.sequential.into()),
+            ("parallel", self.parallel.into()),
+            ("speedup", self.speedup().into()),
+            ("efficiency", self.efficiency().into()),
+            ("serial_fraction", self.serial_fraction().into()),
+        ]
+    }
+}
+
+/// Messages per second through one channel at one batch size
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThroughputReport {
+    /// Channel and operations used
+    pub name: String,
+    /// Messages moved per send and receive call
+    pub batch: usize,
+    /// Messages sent in one run
+    pub messages: usize,
+    pub repetitions: usize,
+    /// Time of one run, from the first send to the last receive
+    pub time: Stats,
+}
+
+impl ThroughputReport {
+    /// Messages per second of the median run
+    pub fn messages_per_sec(&self) -> f64 {
+        self.messages as f64 / self.time.median.as_secs_f64()
+    }
+}
+
+/// Name of the per-message baseline in throughput reports
+pub const STD_MPSC: &str = "std mpsc";
+
+/// Sends `config.units` messages one by one through `std::sync::mpsc`
+pub fn std_mpsc_throughput(config: &BenchConfig) -> ThroughputReport {
+    let messages = config.units;
+    let time = measure(config, || {
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                for value in 0..messages {
+                    sender.send(value).unwrap();
+                }
+            });
+            let received = receiver.iter().map(black_box).count();
+            assert_eq!(received, messages);
+        });
+    });
+    ThroughputReport {
+        name: STD_MPSC.to_string(),
+        batch: 1,
+        messages,
+        repetitions: config.repetitions,
+        time,
+    }
+}
+
+/// Sends `config.units` messages through an unbounded [`mpmc`] channel,
+/// `batch` messages per `send_batch` and at most `batch` per `recv_batch`
+pub fn batched_throughput(batch: usize, config: &BenchConfig) -> ThroughputReport {
+    assert!(batch > 0, "a batch needs room for one message");
+    let messages = config.units;
+    let time = measure(config, || {
+        let (sender, receiver) = mpmc::unbounded();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                let mut next = 0;
+                while next < messages {
+                    let end = (next + batch).min(messages);
+                    sender.send_batch(next..end).unwrap();
+                    next = end;
+                }
+            });
+            let mut received = 0;
+            while let Ok(values) = receiver.recv_batch(batch, Duration::from_secs(60)) {
+                received += black_box(values).len();
+            }
+            assert_eq!(received, messages);
+        });
+    });
+    ThroughputReport {
+        name: "mpmc batch".to_string(),
+        batch,
+        messages,
+        repetitions: config.repetitions,
+        time,
+    }
+}
+
+/// Pushes `config.units` messages one by one through a [`spsc`] ring of `capacity` slots
+pub fn spsc_throughput(capacity: usize, config: &BenchConfig) -> ThroughputReport {
+    let messages = config.units;
+    let time = measure(config, || {
+        let (producer, consumer) = spsc::channel(capacity);
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                for value in 0..messages {
+                    producer.push(value).unwrap();
+                }
+            });
+            let received = consumer.iter().map(black_box).count();
+            assert_eq!(received, messages);
+        });
+    });
+    ThroughputReport {
+        name: "spsc ring".to_string(),
+        batch: 1,
+        messages,
+        repetitions: config.repetitions,
+        time,
+    }
+}
+
+/// Runs the std baseline and the ring buffer, then the batched channel at every batch size
+pub fn channel_throughput(
+    batches: &[usize],
+    capacity: usize,
+    config: &BenchConfig,
+) -> Vec<ThroughputReport> {
+    [
+        std_mpsc_throughput(config),
+        spsc_throughput(capacity, config),
+    ]
+    .into_iter()
+    .chain(
+        batches
+            .iter()
+            .map(|&batch| batched_throughput(batch, config)),
+    )
+    .collect()
+}
+
+/// Throughput of `report` relative to the std baseline among `reports`, if any
+fn versus_std(report: &ThroughputReport, reports: &[ThroughputReport]) -> Option<f64> {
+    reports
+        .iter()
+        .find(|baseline| baseline.name == STD_MPSC)
+        .map(|baseline| report.messages_per_sec() / baseline.messages_per_sec())
+}
+
+impl Report for ThroughputReport {
+    const COLUMNS: &'static [Column] = &[
+        Column::left("channel", 12),
+        Column::right("batch", 6),
+        Column::right("messages", 10),
+        Column::right("median", 12),
+        Column::right("p95", 12),
+        Column::right("msg/s", 14),
+        Column::right("vs std", 8),
+    ];
+
+    fn cells(&self, reports: &[Self]) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.batch.to_string(),
+            self.messages.to_string(),
+            format!("{:.2?}", self.time.median),
+            format!("{:.2?}", self.time.p95),
+            format!("{:.0}", self.messages_per_sec()),
+            versus_std(self, reports).map_or("-".to_string(), |ratio| format!("{:.2}x", ratio)),
+        ]
+    }
+
+    fn fields(&self, reports: &[Self]) -> Vec<(&'static str, Value<'_>)> {
+        vec![
+            ("channel", self.name.as_str().into()),
+            ("batch", self.batch.into()),
+            ("messages", self.messages.into()),
+            ("repetitions", self.repetitions.into()),
+            ("time", self.time.into()),
+            ("messages_per_sec", self.messages_per_sec().into()),
+            ("vs_std", versus_std(self, reports).into()),
+        ]
+    }
+}
+
+/// Per-thread counters laid out next to each other versus one per cache line
+#[derive(Debug, Clone, PartialEq)]
+pub struct FalseSharingReport {
+    pub threads: usize,
+    /// Increments per thread
+    pub increments: usize,
+    pub repetitions: usize,
+    /// Time of a run with the counters in one contiguous array
+    pub adjacent: Stats,
+    /// Time of a run with every counter in a [`CachePadded`]
+    pub padded: Stats,
+}
+
+impl FalseSharingReport {
+    /// Adjacent median time divided by padded median time; above 1.0 is the
+    /// cost of false sharing
+    pub fn slowdown(&self) -> f64 {
+        self.adjacent.median.as_secs_f64() / self.padded.median.as_secs_f64()
+    }
+}
+
+/// Runs `threads` threads that each increment their own counter `config.units`
+/// times, once with adjacent counters and once with padded ones.
+///
+/// No counter is shared, so any slowdown of the adjacent layout comes from
+/// cores fighting over the cache lines the counters happen to share.
+pub fn false_sharing(threads: usize, config: &BenchConfig) -> FalseSharingReport {
+    assert!(threads > 0, "at least one thread is needed");
+    let increments = config.units;
+    let adjacent = measure(config, || {
+        let counters: Vec<AtomicU64> = (0..threads).map(|_| AtomicU64::new(0)).collect();
+        increment_each(&counters.iter().collect::<Vec<_>>(), increments);
+    });
+    let padded = measure(config, || {
+        let counters: Vec<CachePadded<AtomicU64>> = (0..threads)
+            .map(|_| CachePadded::new(AtomicU64::new(0)))
+            .collect();
+        increment_each(
+            &counters
+                .iter()
+                .map(|counter| &**counter)
+                .collect::<Vec<_>>(),
+            increments,
+        );
+    });
+    FalseSharingReport {
+        threads,
+        increments,
+        repetitions: config.repetitions,
+        adjacent,
+        padded,
+    }
+}
+
+/// Spawns one thread per counter that increments it `increments` times
+fn increment_each(counters: &[&AtomicU64], increments: usize) {
+    thread::scope(|scope| {
+        for counter in counters {
+            scope.spawn(move || {
+                for _ in 0..increments {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    assert!(
+        counters
+            .iter()
+            .all(|counter| counter.load(Ordering::Relaxed) == increments as u64)
+    );
+}
+
+impl Report for FalseSharingReport {
+    const COLUMNS: &'static [Column] = &[
+        Column::right("threads", 7),
+        Column::right("increments", 12),
+        Column::right("adjacent med", 14),
+        Column::right("adjacent p95", 14),
+        Column::right("padded med", 12),
+        Column::right("padded p95", 12),
+        Column::right("slowdown", 9),
+    ];
+
+    fn cells(&self, _: &[Self]) -> Vec<String> {
+        vec![
+            self.threads.to_string(),
+            self.increments.to_string(),
+            format!("{:.2?}", self.adjacent.median),
+            format!("{:.2?}", self.adjacent.p95),
+            format!("{:.2?}", self.padded.median),
+            format!("{:.2?}", self.padded.p95),
+            format!("{:.2}x", self.slowdown()),
+        ]
+    }
+
+    fn fields(&self, _: &[Self]) -> Vec<(&'static str, Value<'_>)> {
+        vec![
+            ("threads", self.threads.into()),
+            ("increments", self.increments.into()),
+            ("repetitions", self.repetitions.into()),
+            ("cache_line", mem::align_of::<CachePadded<u8>>().into()),
+            ("adjacent", self.adjacent.into()),
+            ("padded", self.padded.into()),
+            ("slowdown", self.slowdown().into()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn stats_of_samples() {
+        let samples: Vec<Duration> = (1..=20).map(ms).collect();
+        let stats = Stats::from_samples(&samples);
+        assert_eq!(stats.min, ms(1));
+        assert_eq!(stats.max, ms(20));
+        assert_eq!(stats.median, Duration::from_micros(10_500));
+        assert_eq!(stats.p95, ms(19));
+        assert_eq!(Stats::from_samples(&[ms(7)]).p95, ms(7));
+    }
+
+    #[test]
+    fn parallel_run_covers_every_unit_once() {
+        let processed = AtomicUsize::new(0);
+        let config = BenchConfig {
+            units: 10,
+            warmup: 0,
+            repetitions: 1,
+        };
+        compare("count", 3, &config, |units| {
+            processed.fetch_add(units.len(), Ordering::Relaxed);
+        });
+        // One sequential and one parallel run
+        assert_eq!(processed.load(Ordering::Relaxed), 20);
+    }
+
+    #[test]
+    fn sleeping_workload_scales() {
+        let config = BenchConfig {
+            units: 4,
+            warmup: 0,
+            repetitions: 1,
+        };
+        let report = Workload::Sleep(ms(20)).compare(4, &config);
+        // Sleeping threads do not compete for the CPU
+        assert!(report.speedup() > 2.0, "speedup {}", report.speedup());
+        assert!(report.serial_fraction().unwrap() < 0.5);
+    }
+
+    #[test]
+    fn karp_flatt_metric() {
+        let stats = |millis| Stats::from_samples(&[ms(millis)]);
+        let report = BenchReport {
+            name: "synthetic".into(),
+            threads: 4,
+            units: 4,
+            repetitions: 1,
+            sequential: stats(100),
+            parallel: stats(40),
+        };
+        assert!((report.speedup() - 2.5).abs() < 1e-9);
+        assert!((report.efficiency() - 0.625).abs() < 1e-9);
+        // (1/2.5 - 1/4) / (1 - 1/4) = 0.2, so at most 5x with unlimited threads
+        assert!((report.serial_fraction().unwrap() - 0.2).abs() < 1e-9);
+        assert!((report.amdahl_limit().unwrap() - 5.0).abs() < 1e-9);
+
+        let csv = to_csv(std::slice::from_ref(&report));
+        assert_eq!(csv.lines().count(), 2);
+        assert!(
+            csv.lines()
+                .nth(1)
+                .unwrap()
+                .starts_with("synthetic,4,4,1,100000000,")
+        );
+        let json = to_json(std::slice::from_ref(&report));
+        assert!(json.starts_with("[{\"workload\":\"synthetic\",\"threads\":4,"));
+        assert!(json.contains("\"serial_fraction\":0.200000"));
+
+        // A run too fast for the clock has no meaningful speedup
+        let instant = BenchReport {
+            parallel: stats(0),
+            ..report
+        };
+        let json = to_json(std::slice::from_ref(&instant));
+        assert!(json.contains("\"speedup\":null,\"efficiency\":null,"));
+        let csv = to_csv(&[instant]);
+        assert!(csv.lines().nth(1).unwrap().contains(",0,0,,,"));
+    }
+
+    #[test]
+    fn renderers_escape_text() {
+        let report = BenchReport {
+            name: "a \"b\", c".into(),
+            threads: 1,
+            units: 1,
+            repetitions: 1,
+            sequential: Stats::from_samples(&[ms(1)]),
+            parallel: Stats::from_samples(&[ms(1)]),
+        };
+        let reports = [report];
+        assert!(to_json(&reports).starts_with("[{\"workload\":\"a \\\"b\\\", c\","));
+        assert!(
+            to_csv(&reports)
+                .lines()
+                .nth(1)
+                .unwrap()
+                .starts_with("\"a \"\"b\"\", c\",1,")
+        );
+        assert!(
+            to_table(&reports)
+                .lines()
+                .nth(1)
+                .unwrap()
+                .starts_with("a \"b\", c ")
+        );
+        assert_eq!(to_csv::<BenchReport>(&[]), "");
+    }
+
+    #[test]
+    fn channel_throughput_moves_every_message() {
+        let config = BenchConfig {
+            units: 1000,
+            warmup: 0,
+            repetitions: 1,
+        };
+        let reports = channel_throughput(&[1, 64], 16, &config);
+        let names: Vec<_> = reports
+            .iter()
+            .map(|report| (report.name.as_str(), report.batch))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (STD_MPSC, 1),
+                ("spsc ring", 1),
+                ("mpmc batch", 1),
+                ("mpmc batch", 64)
+            ]
+        );
+        assert!(reports.iter().all(|report| report.messages_per_sec() > 0.0));
+
+        let csv = to_csv(&reports);
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.lines().nth(1).unwrap().ends_with(",1.000000"));
+        let json = to_json(&reports);
+        assert!(json.starts_with("[{\"channel\":\"std mpsc\",\"batch\":1,\"messages\":1000,"));
+        assert!(to_table(&reports).contains("1.00x"));
+    }
+
+    #[test]
+    fn false_sharing_runs_both_layouts() {
+        let config = BenchConfig {
+            units: 10_000,
+            warmup: 0,
+            repetitions: 3,
+        };
+        let report = false_sharing(3, &config);
+        assert_eq!(report.threads, 3);
+        assert!(report.slowdown() > 0.0);
+        let csv = to_csv(std::slice::from_ref(&report));
+        assert!(csv.lines().nth(1).unwrap().starts_with("3,10000,3,"));
+        assert!(to_json(&[report]).contains("\"cache_line\":"));
+    }
+}
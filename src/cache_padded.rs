@@ -0,0 +1,89 @@
+//! Values aligned to their own cache line.
+//!
+//! Two atomics written by different threads but sharing a cache line make the
+//! line bounce between cores even though the threads never touch each other's
+//! data. Wrapping each of them in [`CachePadded`] gives each its own line.
+//! `bench false-sharing` measures what that costs on the host machine.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+/// Pads and aligns a value to the cache line size of the target.
+///
+/// x86_64 and aarch64 use 128 bytes: the former prefetches lines in adjacent
+/// pairs, the latter has 128-byte lines on some cores (Apple M series). Other
+/// targets use 64 bytes.
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64"), repr(align(128)))]
+#[cfg_attr(
+    not(any(target_arch = "x86_64", target_arch = "aarch64")),
+    repr(align(64))
+)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CachePadded<T> {
+    value: T,
+}
+
+impl<T> CachePadded<T> {
+    /// Pads `value`
+    pub const fn new(value: T) -> CachePadded<T> {
+        CachePadded { value }
+    }
+
+    /// Removes the padding
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T> From<T> for CachePadded<T> {
+    fn from(value: T) -> Self {
+        CachePadded::new(value)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for CachePadded<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachePadded")
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[test]
+    fn neighbours_never_share_a_line() {
+        let line = mem::align_of::<CachePadded<u8>>();
+        assert!(line == 64 || line == 128);
+        assert_eq!(mem::size_of::<CachePadded<AtomicU64>>(), line);
+        let pair = [CachePadded::new(1u8), CachePadded::new(2u8)];
+        let distance = &*pair[1] as *const u8 as usize - &*pair[0] as *const u8 as usize;
+        assert_eq!(distance, line);
+    }
+
+    #[test]
+    fn derefs_to_the_value() {
+        let mut padded = CachePadded::from(AtomicU64::new(1));
+        padded.fetch_add(1, Ordering::Relaxed);
+        *padded.get_mut() += 1;
+        assert_eq!(padded.into_inner().into_inner(), 3);
+    }
+}
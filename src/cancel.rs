@@ -0,0 +1,280 @@
+//! Cooperative cancellation and bounded joins for spawned threads.
+//!
+//! [`spawn`] hands the closure a [`CancellationToken`]. The worker checks it
+//! between steps and sleeps through it, so [`CancellableHandle::cancel`] stops
+//! the loop at the next step and wakes a sleeping worker immediately.
+//! [`CancellableHandle::join_timeout`] bounds how long the caller waits.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::deadline;
+use crate::join::{self, JoinError, JoinHandle};
+
+/// Returned by token operations once cancellation has been requested
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("operation was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// A shared flag telling workers to stop; clones observe the same flag
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<TokenState>,
+}
+
+#[derive(Default)]
+struct TokenState {
+    /// Fast path for `is_cancelled`
+    cancelled: AtomicBool,
+    /// Guards the flag for sleepers waiting on `wakeup`
+    lock: Mutex<bool>,
+    wakeup: Condvar,
+}
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Requests cancellation and wakes every thread sleeping on the token
+    pub fn cancel(&self) {
+        let mut cancelled = self.inner.lock.lock().unwrap();
+        *cancelled = true;
+        self.inner.cancelled.store(true, Ordering::Release);
+        self.inner.wakeup.notify_all();
+    }
+
+    /// Whether cancellation has been requested
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Fails with [`Cancelled`] once cancellation has been requested; call it between steps
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sleeps for `duration` unless cancelled first, in which case it returns early
+    pub fn sleep(&self, duration: Duration) -> Result<(), Cancelled> {
+        let deadline = deadline::after(duration);
+        let cancelled = self.inner.lock.lock().unwrap();
+        let (cancelled, _) =
+            deadline::wait_while(&self.inner.wakeup, cancelled, deadline, |cancelled| {
+                !*cancelled
+            });
+        if *cancelled { Err(Cancelled) } else { Ok(()) }
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Set by the worker when it returns or panics
+struct Finished {
+    done: Mutex<bool>,
+    signal: Condvar,
+}
+
+/// Marks the worker finished when dropped, which also covers unwinding
+struct FinishGuard(Arc<Finished>);
+
+impl Drop for FinishGuard {
+    fn drop(&mut self) {
+        *self.0.done.lock().unwrap() = true;
+        self.0.signal.notify_all();
+    }
+}
+
+/// Spawns a worker that receives a token it should check between its steps
+pub fn spawn<F, T>(f: F) -> CancellableHandle<T>
+where
+    F: FnOnce(CancellationToken) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let token = CancellationToken::new();
+    let finished = Arc::new(Finished {
+        done: Mutex::new(false),
+        signal: Condvar::new(),
+    });
+    let worker_token = token.clone();
+    let guard = FinishGuard(Arc::clone(&finished));
+    let handle = join::spawn(move || {
+        let _guard = guard;
+        f(worker_token)
+    });
+    CancellableHandle {
+        handle,
+        token,
+        finished,
+    }
+}
+
+/// Handle of a thread spawned with [`spawn`]
+pub struct CancellableHandle<T> {
+    handle: JoinHandle<T>,
+    token: CancellationToken,
+    finished: Arc<Finished>,
+}
+
+/// Why [`CancellableHandle::join_timeout`] did not return a value
+pub enum JoinTimeoutError<T> {
+    /// The worker was still running; the handle is given back
+    Timeout(CancellableHandle<T>),
+    /// The worker panicked
+    Panicked(JoinError),
+}
+
+impl<T> fmt::Debug for JoinTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinTimeoutError::Timeout(_) => f.write_str("Timeout(..)"),
+            JoinTimeoutError::Panicked(error) => f.debug_tuple("Panicked").field(error).finish(),
+        }
+    }
+}
+
+impl<T> fmt::Display for JoinTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinTimeoutError::Timeout(_) => f.write_str("timed out waiting for the thread"),
+            JoinTimeoutError::Panicked(error) => error.fmt(f),
+        }
+    }
+}
+
+impl<T> std::error::Error for JoinTimeoutError<T> {}
+
+impl<T> CancellableHandle<T> {
+    /// Requests cancellation of the worker without waiting for it
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    /// The token handed to the worker
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// Whether the worker has returned or panicked
+    pub fn is_finished(&self) -> bool {
+        *self.finished.done.lock().unwrap()
+    }
+
+    /// Waits for the worker to finish
+    pub fn join(self) -> Result<T, JoinError> {
+        self.handle.join()
+    }
+
+    /// Waits at most `timeout` for the worker to finish
+    pub fn join_timeout(self, timeout: Duration) -> Result<T, JoinTimeoutError<T>> {
+        let deadline = deadline::after(timeout);
+        let done = self.finished.done.lock().unwrap();
+        let (done, timed_out) =
+            deadline::wait_while(&self.finished.signal, done, deadline, |done| !*done);
+        drop(done);
+        if timed_out {
+            return Err(JoinTimeoutError::Timeout(self));
+        }
+        // The worker is past its last statement, so this join does not block for long
+        self.handle.join().map_err(JoinTimeoutError::Panicked)
+    }
+
+    /// Cancels the worker and waits for it to wind down
+    pub fn cancel_and_join(self) -> Result<T, JoinError> {
+        self.cancel();
+        self.join()
+    }
+}
+
+impl<T> fmt::Debug for CancellableHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellableHandle")
+            .field("thread", self.handle.thread())
+            .field("cancelled", &self.token.is_cancelled())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn cancel_wakes_a_sleeping_worker() {
+        let started = Instant::now();
+        let handle = spawn(|token| token.sleep(Duration::from_secs(60)));
+        std::thread::sleep(Duration::from_millis(20));
+        handle.cancel();
+        assert_eq!(handle.join().unwrap(), Err(Cancelled));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn join_timeout_gives_the_handle_back() {
+        let handle = spawn(|token| {
+            let mut steps = 0;
+            while token.check().is_ok() {
+                steps += 1;
+                let _ = token.sleep(Duration::from_millis(5));
+            }
+            steps
+        });
+        let handle = match handle.join_timeout(Duration::from_millis(30)) {
+            Err(JoinTimeoutError::Timeout(handle)) => handle,
+            other => panic!("expected a timeout, got {:?}", other),
+        };
+        assert!(!handle.is_finished());
+        let steps = handle.cancel_and_join().unwrap();
+        assert!(steps > 0);
+    }
+
+    #[test]
+    fn join_timeout_returns_finished_values() {
+        let handle = spawn(|_token| 5);
+        assert_eq!(handle.join_timeout(Duration::from_secs(10)).unwrap(), 5);
+        // Too far away for an `Instant`, so there is no deadline at all
+        let handle = spawn(|token| token.sleep(Duration::MAX));
+        handle.cancel();
+        assert_eq!(handle.join_timeout(Duration::MAX).unwrap(), Err(Cancelled));
+    }
+
+    #[test]
+    fn join_timeout_reports_panics() {
+        let handle = spawn(|_token| -> () { panic!("worker failed") });
+        match handle.join_timeout(Duration::from_secs(10)) {
+            Err(JoinTimeoutError::Panicked(error)) => {
+                assert_eq!(error.message(), Some("worker failed"))
+            }
+            other => panic!("expected a panic, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sleep_completes_without_cancellation() {
+        let token = CancellationToken::new();
+        assert_eq!(token.sleep(Duration::from_millis(1)), Ok(()));
+        token.cancel();
+        assert_eq!(token.check(), Err(Cancelled));
+        assert_eq!(token.sleep(Duration::from_secs(60)), Err(Cancelled));
+    }
+}
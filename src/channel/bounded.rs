@@ -0,0 +1,340 @@
+//! A bounded channel whose senders choose what happens when it is full.
+//!
+//! `std::sync::mpsc::channel` grows without limit and `sync_channel` can only
+//! block. Here the [`Policy`] picked at creation decides: wait for room, fail
+//! immediately, discard the new message, evict the oldest one, or wait up to a
+//! timeout. Discarded messages are handed back in the [`Sent`] outcome and
+//! counted, so producers always know what happened to their data.
+//!
+//! The queue itself is an [`mpmc::bounded`] channel: the policies map onto its
+//! blocking, timed, non-blocking and evicting sends.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvError, RecvTimeoutError, TryRecvError, TrySendError};
+use std::time::Duration;
+
+use crate::channel::mpmc::{self, SendTimeoutError};
+use crate::channel::select::{TryRecv, Waker};
+
+/// Owning iterator over the messages of a receiver
+pub use crate::channel::mpmc::IntoIter;
+
+/// What a sender does when the channel is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Wait until the receiver makes room
+    Block,
+    /// Return [`SendError::Full`] right away
+    FailFast,
+    /// Keep the queue as it is and discard the new message
+    DropNewest,
+    /// Evict the oldest queued message to make room for the new one
+    DropOldest,
+    /// Wait at most this long, then return [`SendError::Timeout`]
+    BlockTimeout(Duration),
+}
+
+/// What happened to a message the channel accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sent<T> {
+    /// The message was queued
+    Queued,
+    /// The channel was full and the message itself was discarded
+    DroppedNewest(T),
+    /// The message was queued after evicting this older one
+    DroppedOldest(T),
+}
+
+/// Why a message could not be sent; carries the message back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError<T> {
+    /// The channel was full under [`Policy::FailFast`]
+    Full(T),
+    /// The channel stayed full for the whole [`Policy::BlockTimeout`]
+    Timeout(T),
+    /// The receiver was dropped
+    Disconnected(T),
+}
+
+impl<T> SendError<T> {
+    /// The message that was not sent
+    pub fn into_inner(self) -> T {
+        match self {
+            SendError::Full(value) | SendError::Timeout(value) | SendError::Disconnected(value) => {
+                value
+            }
+        }
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Full(_) => f.write_str("sending on a full channel"),
+            SendError::Timeout(_) => f.write_str("timed out waiting for room in the channel"),
+            SendError::Disconnected(_) => f.write_str("sending on a channel without receiver"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+
+/// Creates a channel holding at most `capacity` messages, applying `policy` when full
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn channel<T>(capacity: usize, policy: Policy) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = mpmc::bounded(capacity);
+    let sender = Sender {
+        inner: sender,
+        policy,
+        dropped: Arc::new(AtomicU64::new(0)),
+    };
+    (sender, Receiver { inner: receiver })
+}
+
+/// The sending half; clone it to get more producers
+pub struct Sender<T> {
+    inner: mpmc::Sender<T>,
+    policy: Policy,
+    /// Messages discarded by the drop policies, shared by every clone
+    dropped: Arc<AtomicU64>,
+}
+
+impl<T> Sender<T> {
+    /// Sends `value`, applying the channel's policy if it is full
+    pub fn send(&self, value: T) -> Result<Sent<T>, SendError<T>> {
+        match self.policy {
+            Policy::Block => self
+                .inner
+                .send(value)
+                .map(|()| Sent::Queued)
+                .map_err(|mpsc::SendError(value)| SendError::Disconnected(value)),
+            Policy::BlockTimeout(timeout) => self
+                .inner
+                .send_timeout(value, timeout)
+                .map(|()| Sent::Queued)
+                .map_err(|error| match error {
+                    SendTimeoutError::Timeout(value) => SendError::Timeout(value),
+                    SendTimeoutError::Disconnected(value) => SendError::Disconnected(value),
+                }),
+            Policy::FailFast => match self.inner.try_send(value) {
+                Ok(()) => Ok(Sent::Queued),
+                Err(TrySendError::Full(value)) => Err(SendError::Full(value)),
+                Err(TrySendError::Disconnected(value)) => Err(SendError::Disconnected(value)),
+            },
+            Policy::DropNewest => match self.inner.try_send(value) {
+                Ok(()) => Ok(Sent::Queued),
+                Err(TrySendError::Full(value)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    Ok(Sent::DroppedNewest(value))
+                }
+                Err(TrySendError::Disconnected(value)) => Err(SendError::Disconnected(value)),
+            },
+            Policy::DropOldest => match self.inner.force_send(value) {
+                Ok(None) => Ok(Sent::Queued),
+                Ok(Some(oldest)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    Ok(Sent::DroppedOldest(oldest))
+                }
+                Err(mpsc::SendError(value)) => Err(SendError::Disconnected(value)),
+            },
+        }
+    }
+
+    /// Maximum number of queued messages
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity().expect("the channel is bounded")
+    }
+
+    /// Number of queued messages
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Whether no message is queued
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether another message would trigger the overflow policy
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity()
+    }
+
+    /// The overflow policy of the channel
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    /// Messages discarded by [`Policy::DropNewest`] or [`Policy::DropOldest`] so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender {
+            inner: self.inner.clone(),
+            policy: self.policy,
+            dropped: Arc::clone(&self.dropped),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+/// The receiving half
+pub struct Receiver<T> {
+    inner: mpmc::Receiver<T>,
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until a message is available or every sender is gone
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.inner.recv()
+    }
+
+    /// Returns a queued message without blocking
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.inner.try_recv()
+    }
+
+    /// Waits at most `timeout` for a message
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.inner.recv_timeout(timeout)
+    }
+
+    /// Iterates over messages until every sender is gone and the queue is drained
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.inner.iter()
+    }
+
+    /// Maximum number of queued messages
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity().expect("the channel is bounded")
+    }
+
+    /// Number of queued messages
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Whether no message is queued
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+impl<T> TryRecv for Receiver<T> {
+    type Item = T;
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        Receiver::try_recv(self)
+    }
+
+    fn register(&self, waker: &Waker) -> bool {
+        self.inner.register(waker)
+    }
+
+    fn unregister(&self, waker: &Waker) {
+        self.inner.unregister(waker);
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        self.inner.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Instant;
+
+    /// Fills a fresh channel to its capacity of 2
+    fn full(policy: Policy) -> (Sender<i32>, Receiver<i32>) {
+        let (sender, receiver) = channel(2, policy);
+        assert_eq!(sender.send(1), Ok(Sent::Queued));
+        assert_eq!(sender.send(2), Ok(Sent::Queued));
+        assert!(sender.is_full());
+        (sender, receiver)
+    }
+
+    #[test]
+    fn block_waits_for_room() {
+        let (sender, receiver) = full(Policy::Block);
+        let producer = thread::spawn(move || sender.send(3));
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(receiver.recv(), Ok(1));
+        assert_eq!(producer.join().unwrap(), Ok(Sent::Queued));
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn fail_fast_and_timeout_give_the_value_back() {
+        let (sender, receiver) = full(Policy::FailFast);
+        assert_eq!(sender.send(3), Err(SendError::Full(3)));
+        assert_eq!(receiver.len(), 2);
+
+        let (sender, _receiver) = full(Policy::BlockTimeout(Duration::from_millis(20)));
+        let started = Instant::now();
+        assert_eq!(sender.send(3), Err(SendError::Timeout(3)));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+
+        // Too long for an `Instant`: waits like `Policy::Block` instead of panicking
+        let (sender, receiver) = channel(1, Policy::BlockTimeout(Duration::MAX));
+        assert_eq!(sender.send(1), Ok(Sent::Queued));
+        assert_eq!(receiver.recv_timeout(Duration::MAX), Ok(1));
+    }
+
+    #[test]
+    fn drop_policies_report_the_discarded_message() {
+        let (sender, receiver) = full(Policy::DropNewest);
+        assert_eq!(sender.send(3), Ok(Sent::DroppedNewest(3)));
+        assert_eq!(sender.dropped(), 1);
+        assert_eq!(receiver.try_recv(), Ok(1));
+
+        let (sender, receiver) = full(Policy::DropOldest);
+        assert_eq!(sender.send(3), Ok(Sent::DroppedOldest(1)));
+        assert_eq!(sender.send(4), Ok(Sent::DroppedOldest(2)));
+        assert_eq!(sender.dropped(), 2);
+        drop(sender);
+        assert_eq!(receiver.into_iter().collect::<Vec<_>>(), vec![3, 4]);
+    }
+
+    #[test]
+    fn dropping_the_receiver_wakes_blocked_senders() {
+        let (sender, receiver) = full(Policy::Block);
+        let producer = thread::spawn(move || sender.send(3));
+        thread::sleep(Duration::from_millis(10));
+        drop(receiver);
+        assert_eq!(producer.join().unwrap(), Err(SendError::Disconnected(3)));
+    }
+}
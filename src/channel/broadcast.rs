@@ -0,0 +1,379 @@
+//! A broadcast channel: every subscriber receives every message.
+//!
+//! Messages live in one bounded ring buffer shared by all subscribers; each
+//! [`Receiver`] only remembers the sequence number of the next message it
+//! wants. When the buffer is full the oldest message is overwritten, and a
+//! subscriber that had not read it yet gets [`RecvError::Lagged`] with the
+//! number of messages it missed before continuing with the oldest one still
+//! buffered. A subscriber created with [`Sender::subscribe`] or by cloning a
+//! receiver starts at the next message sent or at the clone's position.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Buffer and bookkeeping shared by the sender and every subscriber
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// Signalled on every send and when the sender is dropped
+    available: Condvar,
+}
+
+struct State<T> {
+    /// The last `capacity` messages, oldest first
+    buffer: VecDeque<T>,
+    capacity: usize,
+    /// Sequence number of `buffer[0]`
+    head: u64,
+    /// Number of live receivers
+    receivers: usize,
+    /// Whether the sender was dropped
+    closed: bool,
+}
+
+impl<T> State<T> {
+    /// Sequence number the next message will get
+    fn tail(&self) -> u64 {
+        self.head + self.buffer.len() as u64
+    }
+}
+
+/// Returned by [`Sender::send`] when nobody is subscribed; carries the value back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("broadcasting without subscribers")
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+
+/// Why [`Receiver::recv`] did not return a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// The subscriber fell behind and this many messages were overwritten;
+    /// the next call returns the oldest message still buffered
+    Lagged(u64),
+    /// The sender was dropped and every buffered message has been read
+    Closed,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Lagged(missed) => {
+                write!(f, "subscriber lagged behind by {} messages", missed)
+            }
+            RecvError::Closed => f.write_str("the broadcast channel is closed"),
+        }
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+/// Why [`Receiver::try_recv`] did not return a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// No new message yet
+    Empty,
+    /// See [`RecvError::Lagged`]
+    Lagged(u64),
+    /// See [`RecvError::Closed`]
+    Closed,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("no new message"),
+            TryRecvError::Lagged(missed) => RecvError::Lagged(*missed).fmt(f),
+            TryRecvError::Closed => RecvError::Closed.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for TryRecvError {}
+
+/// Creates a broadcast channel keeping the last `capacity` messages
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(
+        capacity > 0,
+        "a broadcast channel needs room for one message"
+    );
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+            head: 0,
+            receivers: 1,
+            closed: false,
+        }),
+        available: Condvar::new(),
+    });
+    let receiver = Receiver {
+        shared: Arc::clone(&shared),
+        next: 0,
+    };
+    (Sender { shared }, receiver)
+}
+
+/// The single producing half
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Clone> Sender<T> {
+    /// Broadcasts `value` and returns how many subscribers will see it
+    pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.receivers == 0 {
+            return Err(SendError(value));
+        }
+        if state.buffer.len() == state.capacity {
+            // Overwrite the oldest message; subscribers still on it will lag
+            state.buffer.pop_front();
+            state.head += 1;
+        }
+        state.buffer.push_back(value);
+        let receivers = state.receivers;
+        drop(state);
+        self.shared.available.notify_all();
+        Ok(receivers)
+    }
+
+    /// Creates a subscriber that receives every message sent from now on
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receivers += 1;
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            next: state.tail(),
+        }
+    }
+
+    /// Number of live subscribers
+    pub fn receiver_count(&self) -> usize {
+        self.shared.state.lock().unwrap().receivers
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.available.notify_all();
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.shared.state.lock().unwrap();
+        f.debug_struct("Sender")
+            .field("capacity", &state.capacity)
+            .field("receivers", &state.receivers)
+            .finish()
+    }
+}
+
+/// A subscriber; clone it to get another subscriber at the same position
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    /// Sequence number of the next message to read
+    next: u64,
+}
+
+impl<T: Clone> Receiver<T> {
+    /// Blocks until the next message, reporting lag and the end of the channel
+    pub fn recv(&mut self) -> Result<T, RecvError> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            match take(&mut self.next, &state) {
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Lagged(missed)) => return Err(RecvError::Lagged(missed)),
+                Err(TryRecvError::Closed) => return Err(RecvError::Closed),
+                Ok(value) => return Ok(value),
+            }
+            state = self.shared.available.wait(state).unwrap();
+        }
+    }
+
+    /// Returns the next message if it has already been sent
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let state = self.shared.state.lock().unwrap();
+        take(&mut self.next, &state)
+    }
+
+    /// Iterates over the messages until the channel closes, skipping over lag
+    pub fn iter(&mut self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+}
+
+/// Reads the message numbered `next` and moves `next` past it or past the lag
+fn take<T: Clone>(next: &mut u64, state: &State<T>) -> Result<T, TryRecvError> {
+    if *next < state.head {
+        let missed = state.head - *next;
+        *next = state.head;
+        return Err(TryRecvError::Lagged(missed));
+    }
+    if *next < state.tail() {
+        let value = state.buffer[(*next - state.head) as usize].clone();
+        *next += 1;
+        return Ok(value);
+    }
+    if state.closed {
+        Err(TryRecvError::Closed)
+    } else {
+        Err(TryRecvError::Empty)
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().receivers += 1;
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            next: self.next,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().receivers -= 1;
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("next", &self.next)
+            .finish()
+    }
+}
+
+/// Borrowing iterator returned by [`Receiver::iter`]
+pub struct Iter<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T: Clone> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            match self.receiver.recv() {
+                Ok(value) => return Some(value),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Owning iterator over the messages of a subscriber, skipping over lag
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T: Clone> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.iter().next()
+    }
+}
+
+impl<T: Clone> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+impl<'a, T: Clone> IntoIterator for &'a mut Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn every_subscriber_gets_every_message() {
+        let (sender, receiver) = channel(16);
+        let subscribers: Vec<_> = (0..3)
+            .map(|_| {
+                let receiver = sender.subscribe();
+                thread::spawn(move || receiver.into_iter().collect::<Vec<i32>>())
+            })
+            .collect();
+        drop(receiver);
+        for value in 0..10 {
+            assert_eq!(sender.send(value), Ok(3));
+        }
+        drop(sender);
+        for subscriber in subscribers {
+            assert_eq!(subscriber.join().unwrap(), (0..10).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn slow_subscriber_lags() {
+        let (sender, mut receiver) = channel(2);
+        for value in 0..5 {
+            sender.send(value).unwrap();
+        }
+        assert_eq!(receiver.recv(), Err(RecvError::Lagged(3)));
+        assert_eq!(receiver.recv(), Ok(3));
+        assert_eq!(receiver.try_recv(), Ok(4));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+        drop(sender);
+        assert_eq!(receiver.recv(), Err(RecvError::Closed));
+    }
+
+    #[test]
+    fn late_subscribers_only_see_newer_messages() {
+        let (sender, mut early) = channel(8);
+        sender.send("before").unwrap();
+        let mut late = sender.subscribe();
+        let mut copy = early.clone();
+        sender.send("after").unwrap();
+        assert_eq!(early.recv(), Ok("before"));
+        assert_eq!(late.recv(), Ok("after"));
+        assert_eq!(copy.recv(), Ok("before"));
+        assert_eq!(sender.receiver_count(), 3);
+    }
+
+    #[test]
+    fn iterator_skips_lag_and_stops_when_closed() {
+        let (sender, mut receiver) = channel(3);
+        for value in 0..6 {
+            sender.send(value).unwrap();
+        }
+        drop(sender);
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn send_fails_without_subscribers() {
+        let (sender, receiver) = channel(1);
+        drop(receiver);
+        assert_eq!(sender.send(1), Err(SendError(1)));
+        let _receiver = sender.subscribe();
+        assert_eq!(sender.send(2), Ok(1));
+    }
+}
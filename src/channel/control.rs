@@ -0,0 +1,356 @@
+//! A channel that carries control messages next to its data.
+//!
+//! Instead of agreeing on a magic payload such as `"done"`, producers call
+//! [`Sender::close`] (or [`Sender::flush`], [`Sender::error`]) and the receiver
+//! gets a typed [`Message`]. When the channel ends, the receiver learns why
+//! through an [`EndReason`]: every sender was dropped, a sender closed it, or
+//! a sender panicked. Data sent before the end is always delivered first, so a
+//! consumer looping on [`Receiver::recv`] drains the queue before it exits.
+
+use std::cell::Cell;
+use std::fmt;
+use std::sync::{Arc, RwLock, mpsc};
+use std::thread;
+
+/// What travels through the underlying `mpsc` channel
+enum Packet<T> {
+    Data(T),
+    Flush,
+    Error(String),
+    /// Terminal packet, always the last one in the queue
+    End(EndReason),
+}
+
+/// A message delivered to the receiver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message<T> {
+    /// A payload
+    Data(T),
+    /// The producer asks the consumer to flush whatever it buffers
+    Flush,
+    /// The producer reports a problem but keeps the channel open
+    Error(String),
+}
+
+/// Why a channel stopped delivering messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndReason {
+    /// Every sender was dropped without closing the channel
+    Disconnected,
+    /// A sender called [`Sender::close`]
+    Closed,
+    /// A sender was dropped while its thread was panicking
+    SenderPanicked,
+}
+
+impl fmt::Display for EndReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EndReason::Disconnected => f.write_str("all senders were dropped"),
+            EndReason::Closed => f.write_str("the channel was closed"),
+            EndReason::SenderPanicked => f.write_str("a sender panicked"),
+        }
+    }
+}
+
+impl std::error::Error for EndReason {}
+
+/// Returned by [`Sender::send`] with the value that could not be sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError<T> {
+    /// The channel was closed, explicitly or by a panicking sender
+    Closed(T),
+    /// The receiver was dropped
+    Disconnected(T),
+}
+
+impl<T> SendError<T> {
+    /// The value that was not sent
+    pub fn into_inner(self) -> T {
+        match self {
+            SendError::Closed(value) | SendError::Disconnected(value) => value,
+        }
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Closed(_) => f.write_str("sending on a closed channel"),
+            SendError::Disconnected(_) => f.write_str("sending on a channel without receiver"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+
+/// Creates a channel with control messages
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = mpsc::channel();
+    let sender = Sender {
+        inner: sender,
+        ended: Arc::new(RwLock::new(None)),
+    };
+    let receiver = Receiver {
+        inner: receiver,
+        ended: Cell::new(None),
+    };
+    (sender, receiver)
+}
+
+/// The sending half; clone it to get more producers
+pub struct Sender<T> {
+    inner: mpsc::Sender<Packet<T>>,
+    /// Set once by whoever ends the channel. Senders hold the read lock while
+    /// sending so that no packet can overtake the terminal one.
+    ended: Arc<RwLock<Option<EndReason>>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends a payload
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let ended = self.ended.read().unwrap();
+        if ended.is_some() {
+            return Err(SendError::Closed(value));
+        }
+        self.inner
+            .send(Packet::Data(value))
+            .map_err(|error| match error.0 {
+                Packet::Data(value) => SendError::Disconnected(value),
+                _ => unreachable!("a data packet comes back as a data packet"),
+            })
+    }
+
+    /// Asks the consumer to flush; returns `false` if the channel has ended
+    pub fn flush(&self) -> bool {
+        self.send_control(Packet::Flush)
+    }
+
+    /// Reports an error without ending the channel; returns `false` if the channel has ended
+    pub fn error(&self, message: impl Into<String>) -> bool {
+        self.send_control(Packet::Error(message.into()))
+    }
+
+    /// Ends the channel for every sender; the receiver sees [`EndReason::Closed`]
+    /// after the messages already sent
+    pub fn close(&self) {
+        self.end(EndReason::Closed);
+    }
+
+    /// Whether the channel was closed, explicitly or by a panicking sender
+    pub fn is_closed(&self) -> bool {
+        self.ended.read().unwrap().is_some()
+    }
+
+    /// Sends a control packet unless the channel has ended or lost its receiver
+    fn send_control(&self, packet: Packet<T>) -> bool {
+        let ended = self.ended.read().unwrap();
+        ended.is_none() && self.inner.send(packet).is_ok()
+    }
+
+    fn end(&self, reason: EndReason) {
+        // A poisoned lock still holds a valid `Option`
+        let mut ended = self
+            .ended
+            .write()
+            .unwrap_or_else(|error| error.into_inner());
+        if ended.is_none() {
+            *ended = Some(reason);
+            // The receiver may already be gone, then nobody needs to know
+            let _ = self.inner.send(Packet::End(reason));
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender {
+            inner: self.inner.clone(),
+            ended: Arc::clone(&self.ended),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.end(EndReason::SenderPanicked);
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+/// The receiving half
+pub struct Receiver<T> {
+    inner: mpsc::Receiver<Packet<T>>,
+    /// Remembered so every call after the end reports the same reason
+    ended: Cell<Option<EndReason>>,
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until the next message, or returns why the channel ended
+    pub fn recv(&self) -> Result<Message<T>, EndReason> {
+        if let Some(reason) = self.ended.get() {
+            return Err(reason);
+        }
+        let packet = self
+            .inner
+            .recv()
+            .unwrap_or(Packet::End(EndReason::Disconnected));
+        self.unpack(packet)
+    }
+
+    /// Returns the next message if one is queued, `Ok(None)` if the queue is
+    /// empty but the channel still open
+    pub fn try_recv(&self) -> Result<Option<Message<T>>, EndReason> {
+        if let Some(reason) = self.ended.get() {
+            return Err(reason);
+        }
+        match self.inner.try_recv() {
+            Ok(packet) => self.unpack(packet).map(Some),
+            Err(mpsc::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.unpack(Packet::End(EndReason::Disconnected)).map(Some)
+            }
+        }
+    }
+
+    /// Why the channel ended, once the receiver has seen the end
+    pub fn end_reason(&self) -> Option<EndReason> {
+        self.ended.get()
+    }
+
+    /// Iterates over every message until the channel ends
+    pub fn iter(&self) -> impl Iterator<Item = Message<T>> + '_ {
+        std::iter::from_fn(|| self.recv().ok())
+    }
+
+    /// Iterates over the payloads until the channel ends, skipping control messages
+    pub fn data(&self) -> impl Iterator<Item = T> + '_ {
+        self.iter().filter_map(|message| match message {
+            Message::Data(value) => Some(value),
+            Message::Flush | Message::Error(_) => None,
+        })
+    }
+
+    /// Takes every payload that is already queued without blocking
+    pub fn drain(&self) -> Vec<T> {
+        let mut values = Vec::new();
+        while let Ok(Some(message)) = self.try_recv() {
+            if let Message::Data(value) = message {
+                values.push(value);
+            }
+        }
+        values
+    }
+
+    fn unpack(&self, packet: Packet<T>) -> Result<Message<T>, EndReason> {
+        match packet {
+            Packet::Data(value) => Ok(Message::Data(value)),
+            Packet::Flush => Ok(Message::Flush),
+            Packet::Error(message) => Ok(Message::Error(message)),
+            Packet::End(reason) => {
+                self.ended.set(Some(reason));
+                Err(reason)
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("ended", &self.ended.get())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_equal_to_a_sentinel_is_just_data() {
+        let (sender, receiver) = channel();
+        sender.send("done".to_string()).unwrap();
+        sender.send("more".to_string()).unwrap();
+        sender.close();
+        assert_eq!(receiver.recv(), Ok(Message::Data("done".to_string())));
+        assert_eq!(receiver.recv(), Ok(Message::Data("more".to_string())));
+        assert_eq!(receiver.recv(), Err(EndReason::Closed));
+        // The end is sticky
+        assert_eq!(receiver.recv(), Err(EndReason::Closed));
+    }
+
+    #[test]
+    fn close_applies_to_every_clone() {
+        let (sender, receiver) = channel();
+        let other = sender.clone();
+        sender.send(1).unwrap();
+        other.close();
+        assert!(sender.is_closed());
+        assert_eq!(sender.send(2), Err(SendError::Closed(2)));
+        assert!(!sender.flush());
+        assert_eq!(receiver.data().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(receiver.end_reason(), Some(EndReason::Closed));
+    }
+
+    #[test]
+    fn dropping_every_sender_disconnects() {
+        let (sender, receiver) = channel::<i32>();
+        let other = sender.clone();
+        drop(sender);
+        other.send(7).unwrap();
+        drop(other);
+        assert_eq!(receiver.recv(), Ok(Message::Data(7)));
+        assert_eq!(receiver.recv(), Err(EndReason::Disconnected));
+    }
+
+    #[test]
+    fn panicking_sender_ends_the_channel() {
+        let (sender, receiver) = channel();
+        let survivor = sender.clone();
+        let handle = thread::spawn(move || {
+            sender.send(1).unwrap();
+            panic!("producer failed");
+        });
+        assert!(handle.join().is_err());
+        assert_eq!(survivor.send(2), Err(SendError::Closed(2)));
+        assert_eq!(receiver.recv(), Ok(Message::Data(1)));
+        assert_eq!(receiver.recv(), Err(EndReason::SenderPanicked));
+    }
+
+    #[test]
+    fn control_messages_are_delivered_in_order() {
+        let (sender, receiver) = channel();
+        sender.send(1).unwrap();
+        assert!(sender.flush());
+        assert!(sender.error("disk almost full"));
+        sender.send(2).unwrap();
+        let messages: Vec<_> = receiver.try_recv().into_iter().flatten().collect();
+        assert_eq!(messages, vec![Message::Data(1)]);
+        assert_eq!(receiver.recv(), Ok(Message::Flush));
+        assert_eq!(
+            receiver.recv(),
+            Ok(Message::Error("disk almost full".into()))
+        );
+        assert_eq!(receiver.drain(), vec![2]);
+        assert_eq!(receiver.try_recv(), Ok(None));
+        drop(sender);
+        assert_eq!(receiver.try_recv(), Err(EndReason::Disconnected));
+    }
+
+    #[test]
+    fn send_fails_without_receiver() {
+        let (sender, receiver) = channel();
+        drop(receiver);
+        assert_eq!(sender.send(3), Err(SendError::Disconnected(3)));
+    }
+}
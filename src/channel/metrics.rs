@@ -0,0 +1,523 @@
+//! An `mpsc` channel that measures itself.
+//!
+//! Every message is stamped when it is sent. The halves update a shared set
+//! of atomic counters: messages sent and received, the current queue depth
+//! and its high-water mark, and a histogram of send-to-receive latencies with
+//! one bucket per power of two nanoseconds. [`Metrics::snapshot`] reads them
+//! at any time, and a [`Reporter`] thread can hand a snapshot to a callback at
+//! a fixed interval.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvError, RecvTimeoutError, SendError, TryRecvError};
+use std::time::{Duration, Instant};
+
+use crate::cancel::{self, CancellableHandle};
+use crate::channel::select::{SenderWakers, TryRecv, Waker, Wakers};
+use crate::join::JoinError;
+
+/// Number of latency buckets; bucket `i` holds latencies below `2^(i+1)` ns
+const BUCKETS: usize = 64;
+
+/// A message and the moment it was sent
+struct Stamped<T> {
+    value: T,
+    sent_at: Instant,
+}
+
+/// Counters shared by both halves of an instrumented channel
+struct Counters {
+    created: Instant,
+    sent: AtomicU64,
+    received: AtomicU64,
+    high_water: AtomicU64,
+    latency_total_ns: AtomicU64,
+    latency_max_ns: AtomicU64,
+    latency_buckets: [AtomicU64; BUCKETS],
+}
+
+/// Handle to the counters of an instrumented channel; clones share them
+#[derive(Clone)]
+pub struct Metrics {
+    counters: Arc<Counters>,
+}
+
+impl Metrics {
+    fn new() -> Metrics {
+        Metrics {
+            counters: Arc::new(Counters {
+                created: Instant::now(),
+                sent: AtomicU64::new(0),
+                received: AtomicU64::new(0),
+                high_water: AtomicU64::new(0),
+                latency_total_ns: AtomicU64::new(0),
+                latency_max_ns: AtomicU64::new(0),
+                latency_buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            }),
+        }
+    }
+
+    fn record_send(&self) {
+        let counters = &*self.counters;
+        let sent = counters.sent.fetch_add(1, Ordering::Relaxed) + 1;
+        // The receiver may have counted this message already, so never go below zero
+        let depth = sent.saturating_sub(counters.received.load(Ordering::Relaxed));
+        counters.high_water.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    fn record_receive(&self, sent_at: Instant) {
+        let counters = &*self.counters;
+        counters.received.fetch_add(1, Ordering::Relaxed);
+        let latency = sent_at.elapsed().as_nanos().min(u64::MAX as u128) as u64;
+        counters
+            .latency_total_ns
+            .fetch_add(latency, Ordering::Relaxed);
+        counters
+            .latency_max_ns
+            .fetch_max(latency, Ordering::Relaxed);
+        counters.latency_buckets[bucket(latency)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reads every counter.
+    ///
+    /// The counters are read one by one while the channel keeps running, so
+    /// the values can be a few messages apart from each other.
+    pub fn snapshot(&self) -> Snapshot {
+        let counters = &*self.counters;
+        let received = counters.received.load(Ordering::Relaxed);
+        let sent = counters.sent.load(Ordering::Relaxed);
+        Snapshot {
+            elapsed: counters.created.elapsed(),
+            sent,
+            received,
+            depth: sent.saturating_sub(received),
+            high_water: counters.high_water.load(Ordering::Relaxed),
+            latency: Histogram {
+                buckets: counters
+                    .latency_buckets
+                    .iter()
+                    .map(|bucket| bucket.load(Ordering::Relaxed))
+                    .collect(),
+                total: Duration::from_nanos(counters.latency_total_ns.load(Ordering::Relaxed)),
+                max: Duration::from_nanos(counters.latency_max_ns.load(Ordering::Relaxed)),
+            },
+        }
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Metrics").field(&self.snapshot()).finish()
+    }
+}
+
+/// Index of the histogram bucket for a latency in nanoseconds
+fn bucket(nanos: u64) -> usize {
+    (u64::BITS - 1 - nanos.max(1).leading_zeros()) as usize
+}
+
+/// The counters of a channel at one point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// Time since the channel was created
+    pub elapsed: Duration,
+    pub sent: u64,
+    pub received: u64,
+    /// Messages sent but not received yet
+    pub depth: u64,
+    /// Largest depth seen so far
+    pub high_water: u64,
+    /// Send-to-receive latency of the received messages
+    pub latency: Histogram,
+}
+
+impl Snapshot {
+    /// Messages received per second since the channel was created
+    pub fn throughput(&self) -> f64 {
+        per_second(self.received, self.elapsed)
+    }
+
+    /// Messages received per second between `earlier` and this snapshot,
+    /// zero if `earlier` was actually taken later
+    pub fn throughput_since(&self, earlier: &Snapshot) -> f64 {
+        per_second(
+            self.received.saturating_sub(earlier.received),
+            self.elapsed.saturating_sub(earlier.elapsed),
+        )
+    }
+}
+
+fn per_second(messages: u64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        0.0
+    } else {
+        messages as f64 / elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sent {} received {} depth {} (max {}) {:.0} msg/s latency mean {:?} p99 {:?} max {:?}",
+            self.sent,
+            self.received,
+            self.depth,
+            self.high_water,
+            self.throughput(),
+            self.latency.mean(),
+            self.latency.percentile(0.99),
+            self.latency.max,
+        )
+    }
+}
+
+/// Latencies grouped in power-of-two buckets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    /// `buckets[i]` counts latencies in `[2^i, 2^(i+1))` ns, bucket 0 also holds 0 ns
+    pub buckets: Vec<u64>,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl Histogram {
+    /// Number of recorded latencies
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Average latency, zero when nothing was recorded
+    pub fn mean(&self) -> Duration {
+        match self.count() {
+            0 => Duration::ZERO,
+            count => Duration::from_nanos((self.total.as_nanos() / count as u128) as u64),
+        }
+    }
+
+    /// Upper bound of the bucket holding the `quantile` (between 0 and 1),
+    /// capped at the largest latency seen
+    pub fn percentile(&self, quantile: f64) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, &bucket) in self.buckets.iter().enumerate() {
+            seen += bucket;
+            if seen >= rank {
+                let upper = 1u128 << (index + 1);
+                return Duration::from_nanos(upper.min(u64::MAX as u128) as u64).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+/// Creates an instrumented unbounded channel
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = mpsc::channel();
+    pair(Inner::Unbounded(sender), receiver)
+}
+
+/// Creates an instrumented channel holding at most `bound` messages
+pub fn sync_channel<T>(bound: usize) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = mpsc::sync_channel(bound);
+    pair(Inner::Bounded(sender), receiver)
+}
+
+fn pair<T>(sender: Inner<T>, receiver: mpsc::Receiver<Stamped<T>>) -> (Sender<T>, Receiver<T>) {
+    let metrics = Metrics::new();
+    let wakers = Arc::new(Wakers::default());
+    let receiver = Receiver {
+        inner: receiver,
+        metrics: metrics.clone(),
+        wakers: Arc::clone(&wakers),
+    };
+    (
+        Sender {
+            inner: sender,
+            metrics,
+            wakers: SenderWakers(wakers),
+        },
+        receiver,
+    )
+}
+
+enum Inner<T> {
+    Unbounded(mpsc::Sender<Stamped<T>>),
+    Bounded(mpsc::SyncSender<Stamped<T>>),
+}
+
+/// The sending half; clone it to get more producers
+pub struct Sender<T> {
+    inner: Inner<T>,
+    metrics: Metrics,
+    /// Selects parked on the receiver; declared after `inner` to be dropped after it
+    wakers: SenderWakers,
+}
+
+impl<T> Sender<T> {
+    /// Stamps and sends `value`, blocking while a bounded channel is full
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let stamped = Stamped {
+            value,
+            sent_at: Instant::now(),
+        };
+        let result = match &self.inner {
+            Inner::Unbounded(sender) => sender.send(stamped),
+            Inner::Bounded(sender) => sender.send(stamped),
+        };
+        // Only messages that made it into the channel are counted
+        result.map_err(|SendError(stamped)| SendError(stamped.value))?;
+        self.metrics.record_send();
+        self.wakers.0.wake_all();
+        Ok(())
+    }
+
+    /// The counters of this channel
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        let inner = match &self.inner {
+            Inner::Unbounded(sender) => Inner::Unbounded(sender.clone()),
+            Inner::Bounded(sender) => Inner::Bounded(sender.clone()),
+        };
+        Sender {
+            inner,
+            metrics: self.metrics.clone(),
+            wakers: self.wakers.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("metrics", &self.metrics)
+            .finish()
+    }
+}
+
+/// The receiving half
+pub struct Receiver<T> {
+    inner: mpsc::Receiver<Stamped<T>>,
+    metrics: Metrics,
+    wakers: Arc<Wakers>,
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until a message arrives or every sender is gone
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.inner.recv().map(|stamped| self.unstamp(stamped))
+    }
+
+    /// Returns a queued message without blocking
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.inner.try_recv().map(|stamped| self.unstamp(stamped))
+    }
+
+    /// Waits at most `timeout` for a message
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.inner
+            .recv_timeout(timeout)
+            .map(|stamped| self.unstamp(stamped))
+    }
+
+    /// Iterates over messages until every sender is gone
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv().ok())
+    }
+
+    /// The counters of this channel
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    fn unstamp(&self, stamped: Stamped<T>) -> T {
+        self.metrics.record_receive(stamped.sent_at);
+        stamped.value
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("metrics", &self.metrics)
+            .finish()
+    }
+}
+
+impl<T> TryRecv for Receiver<T> {
+    type Item = T;
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        Receiver::try_recv(self)
+    }
+
+    fn register(&self, waker: &Waker) -> bool {
+        self.wakers.register(waker);
+        true
+    }
+
+    fn unregister(&self, waker: &Waker) {
+        self.wakers.unregister(waker);
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+/// Owning iterator over the messages of a receiver
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// A thread handing a [`Snapshot`] of a channel to a callback at a fixed interval
+pub struct Reporter {
+    handle: CancellableHandle<usize>,
+}
+
+impl Reporter {
+    /// Starts reporting every `interval` until [`Reporter::stop`] is called
+    pub fn spawn<F>(metrics: &Metrics, interval: Duration, mut report: F) -> Reporter
+    where
+        F: FnMut(&Snapshot) + Send + 'static,
+    {
+        let metrics = metrics.clone();
+        let handle = cancel::spawn(move |token| {
+            let mut reports = 0;
+            // The sleep returns early with an error once the reporter is stopped
+            while token.sleep(interval).is_ok() {
+                report(&metrics.snapshot());
+                reports += 1;
+            }
+            reports
+        });
+        Reporter { handle }
+    }
+
+    /// Stops the reporter thread and returns how many reports it made, or
+    /// the panic of the report callback that ended it early
+    pub fn stop(self) -> Result<usize, JoinError> {
+        self.handle.cancel_and_join()
+    }
+}
+
+impl fmt::Debug for Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reporter").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::thread;
+
+    #[test]
+    fn counts_depth_and_high_water() {
+        let (sender, receiver) = channel();
+        for value in 0..5 {
+            sender.send(value).unwrap();
+        }
+        assert_eq!(receiver.recv(), Ok(0));
+        assert_eq!(receiver.recv(), Ok(1));
+        let snapshot = receiver.metrics().snapshot();
+        assert_eq!(snapshot.sent, 5);
+        assert_eq!(snapshot.received, 2);
+        assert_eq!(snapshot.depth, 3);
+        assert_eq!(snapshot.high_water, 5);
+        assert_eq!(snapshot.latency.count(), 2);
+    }
+
+    #[test]
+    fn failed_sends_are_not_counted() {
+        let (sender, receiver) = channel();
+        sender.send(1).unwrap();
+        let earlier = receiver.metrics().snapshot();
+        assert_eq!(receiver.recv(), Ok(1));
+        drop(receiver);
+        assert_eq!(sender.send(2), Err(SendError(2)));
+        let snapshot = sender.metrics().snapshot();
+        assert_eq!(
+            (snapshot.sent, snapshot.received, snapshot.depth),
+            (1, 1, 0)
+        );
+        assert_eq!(earlier.throughput_since(&snapshot), 0.0);
+    }
+
+    #[test]
+    fn latency_lands_in_the_right_bucket() {
+        let (sender, receiver) = sync_channel(1);
+        sender.send("slow").unwrap();
+        thread::sleep(Duration::from_millis(2));
+        receiver.recv().unwrap();
+        let latency = receiver.metrics().snapshot().latency;
+        assert!(latency.max >= Duration::from_millis(2));
+        // 2 ms is above 2^20 ns, so nothing can be in the lower buckets
+        assert!(latency.buckets[..20].iter().all(|&count| count == 0));
+        assert!(latency.percentile(0.5) >= Duration::from_millis(2));
+        assert!(latency.percentile(0.5) <= latency.max);
+        assert_eq!(bucket(0), 0);
+        assert_eq!(bucket(1), 0);
+        assert_eq!(bucket(1024), 10);
+    }
+
+    #[test]
+    fn reporter_runs_until_stopped() {
+        let (sender, receiver) = channel();
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reporter = {
+            let reports = Arc::clone(&reports);
+            Reporter::spawn(
+                sender.metrics(),
+                Duration::from_millis(5),
+                move |snapshot| reports.lock().unwrap().push(snapshot.sent),
+            )
+        };
+        for value in 0..3 {
+            sender.send(value).unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+        let made = reporter.stop().unwrap();
+        assert!(made > 0);
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), made);
+        assert!(reports.windows(2).all(|pair| pair[0] <= pair[1]));
+        drop(sender);
+        assert_eq!(receiver.iter().count(), 3);
+    }
+
+    #[test]
+    fn a_panicking_callback_is_reported_by_stop() {
+        let (sender, _receiver) = channel::<i32>();
+        let reporter = Reporter::spawn(sender.metrics(), Duration::from_millis(1), |_| {
+            panic!("report failed")
+        });
+        thread::sleep(Duration::from_millis(20));
+        let error = reporter.stop().unwrap_err();
+        assert_eq!(error.message(), Some("report failed"));
+    }
+}
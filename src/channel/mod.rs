@@ -0,0 +1,15 @@
+//! Channel flavours built on top of, or next to, `std::sync::mpsc`.
+
+pub mod bounded;
+pub mod broadcast;
+pub mod control;
+pub mod metrics;
+pub mod mpmc;
+pub mod mpsc;
+pub mod oneshot;
+pub mod priority;
+pub mod rendezvous;
+pub mod select;
+pub mod spsc;
+pub mod tagged;
+pub mod watch;
@@ -0,0 +1,579 @@
+//! A multi-producer, multi-consumer channel.
+//!
+//! Unlike `std::sync::mpsc`, both halves can be cloned, so several consumers
+//! can take turns draining one work queue. Each message goes to exactly one
+//! receiver. The channel is a `VecDeque` behind a `Mutex` with one `Condvar`
+//! for each direction, and it reuses the error types of `std::sync::mpsc`:
+//! sending fails once every receiver is gone, receiving fails once every
+//! sender is gone and the queue is drained. [`SendTimeoutError`] mirrors the
+//! std type of the same name, which is not stable yet.
+//!
+//! The [`bounded`](crate::channel::bounded) channel adds its overflow
+//! policies on top of [`Sender::send_timeout`] and [`Sender::force_send`].
+//!
+//! [`Sender::send_batch`] and [`Receiver::recv_batch`] move many messages per
+//! lock acquisition and wake-up, which is where a per-message channel spends
+//! most of its time.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::channel::select::{TryRecv, Waker, Wakers};
+use crate::deadline;
+
+/// Queue and bookkeeping shared by every sender and receiver
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// Signalled when a message is queued or the last sender leaves
+    not_empty: Condvar,
+    /// Signalled when a message is taken or the last receiver leaves
+    not_full: Condvar,
+    /// Maximum number of queued messages, `None` for an unbounded channel
+    capacity: Option<usize>,
+    /// Selects parked on a receiver, woken like `not_empty`
+    wakers: Wakers,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    senders: usize,
+    receivers: usize,
+}
+
+impl<T> Shared<T> {
+    fn new(capacity: Option<usize>) -> Arc<Shared<T>> {
+        Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: VecDeque::with_capacity(capacity.unwrap_or(0)),
+                senders: 1,
+                receivers: 1,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+            wakers: Wakers::default(),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap()
+    }
+
+    fn is_full(&self, state: &State<T>) -> bool {
+        self.capacity
+            .is_some_and(|capacity| state.queue.len() >= capacity)
+    }
+
+    /// Waits until the queue has room or every receiver is gone; `true` if `deadline` passed first
+    fn wait_for_room<'a>(
+        &self,
+        state: MutexGuard<'a, State<T>>,
+        deadline: Option<Instant>,
+    ) -> (MutexGuard<'a, State<T>>, bool) {
+        deadline::wait_while(&self.not_full, state, deadline, |state| {
+            state.receivers > 0 && self.is_full(state)
+        })
+    }
+}
+
+/// Returned by [`Sender::send_timeout`]; carries the value back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendTimeoutError<T> {
+    /// The channel stayed full until the timeout
+    Timeout(T),
+    /// Every receiver was dropped
+    Disconnected(T),
+}
+
+impl<T> fmt::Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => f.write_str("timed out waiting on send operation"),
+            SendTimeoutError::Disconnected(_) => f.write_str("sending on a closed channel"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendTimeoutError<T> {}
+
+/// Creates a channel holding at most `capacity` messages; senders block when it is full
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "a bounded channel needs room for one message");
+    pair(Shared::new(Some(capacity)))
+}
+
+/// Creates a channel whose senders never block
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    pair(Shared::new(None))
+}
+
+fn pair<T>(shared: Arc<Shared<T>>) -> (Sender<T>, Receiver<T>) {
+    let receiver = Receiver {
+        shared: Arc::clone(&shared),
+    };
+    (Sender { shared }, receiver)
+}
+
+/// The sending half; clone it to get more producers
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Queues `value`, waiting for room in a bounded channel
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.send_until(value, None).map_err(|error| match error {
+            SendTimeoutError::Timeout(_) => unreachable!("no deadline to pass"),
+            SendTimeoutError::Disconnected(value) => SendError(value),
+        })
+    }
+
+    /// Queues `value`, waiting at most `timeout` for room in a bounded channel
+    pub fn send_timeout(&self, value: T, timeout: Duration) -> Result<(), SendTimeoutError<T>> {
+        self.send_until(value, deadline::after(timeout))
+    }
+
+    fn send_until(&self, value: T, deadline: Option<Instant>) -> Result<(), SendTimeoutError<T>> {
+        let (mut state, timed_out) = self.shared.wait_for_room(self.shared.lock(), deadline);
+        if state.receivers == 0 {
+            return Err(SendTimeoutError::Disconnected(value));
+        }
+        if timed_out {
+            return Err(SendTimeoutError::Timeout(value));
+        }
+        state.queue.push_back(value);
+        drop(state);
+        self.shared.not_empty.notify_one();
+        self.shared.wakers.wake_all();
+        Ok(())
+    }
+
+    /// Queues `value` without waiting, evicting the oldest message if the channel is full.
+    ///
+    /// Returns the evicted message, if any.
+    pub fn force_send(&self, value: T) -> Result<Option<T>, SendError<T>> {
+        let mut state = self.shared.lock();
+        if state.receivers == 0 {
+            return Err(SendError(value));
+        }
+        let evicted = if self.shared.is_full(&state) {
+            state.queue.pop_front()
+        } else {
+            None
+        };
+        state.queue.push_back(value);
+        drop(state);
+        self.shared.not_empty.notify_one();
+        self.shared.wakers.wake_all();
+        Ok(evicted)
+    }
+
+    /// Queues every value of `values`, taking the lock once per run of free slots.
+    ///
+    /// An unbounded channel queues the whole batch at once; a bounded one
+    /// queues as much as fits and waits for room for the rest. Returns the
+    /// number of queued values. If every receiver goes away, the values not
+    /// queued yet are given back. The iterator runs while the lock is held, so
+    /// it should not block.
+    pub fn send_batch<I>(&self, values: I) -> Result<usize, SendError<Vec<T>>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut values = values.into_iter().peekable();
+        let mut queued = 0;
+        let mut state = self.shared.lock();
+        while values.peek().is_some() {
+            state = self.shared.wait_for_room(state, None).0;
+            if state.receivers == 0 {
+                return Err(SendError(values.collect()));
+            }
+            let room = self
+                .shared
+                .capacity
+                .map_or(usize::MAX, |capacity| capacity - state.queue.len());
+            let before = state.queue.len();
+            state.queue.extend(values.by_ref().take(room));
+            let added = state.queue.len() - before;
+            queued += added;
+            if added == 1 {
+                self.shared.not_empty.notify_one();
+            } else {
+                self.shared.not_empty.notify_all();
+            }
+            self.shared.wakers.wake_all();
+        }
+        Ok(queued)
+    }
+
+    /// Queues `value` only if there is room right now
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.shared.lock();
+        if state.receivers == 0 {
+            return Err(TrySendError::Disconnected(value));
+        }
+        if self.shared.is_full(&state) {
+            return Err(TrySendError::Full(value));
+        }
+        state.queue.push_back(value);
+        drop(state);
+        self.shared.not_empty.notify_one();
+        self.shared.wakers.wake_all();
+        Ok(())
+    }
+
+    /// Number of queued messages
+    pub fn len(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+
+    /// Whether no message is queued
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of queued messages, `None` if unbounded
+    pub fn capacity(&self) -> Option<usize> {
+        self.shared.capacity
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            // Wake every receiver so it can notice the disconnect
+            self.shared.not_empty.notify_all();
+            self.shared.wakers.wake_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("len", &self.len())
+            .field("capacity", &self.shared.capacity)
+            .finish()
+    }
+}
+
+/// The receiving half; clone it to get more consumers
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until a message is available or every sender is gone
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.recv_until(None).map_err(|_| RecvError)
+    }
+
+    /// Returns a queued message without blocking
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match self.take(&mut state) {
+            Some(value) => Ok(value),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Waits at most `timeout` for a message
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_until(deadline::after(timeout))
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        deadline::recv_until(
+            &self.shared.not_empty,
+            self.shared.lock(),
+            deadline,
+            |state| self.take(state),
+            |state| state.senders == 0,
+        )
+    }
+
+    /// Waits at most `timeout` for a message, then takes up to `max` queued messages at once.
+    ///
+    /// The returned batch is never empty. Receiving a batch costs one lock
+    /// acquisition and wakes blocked senders once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn recv_batch(&self, max: usize, timeout: Duration) -> Result<Vec<T>, RecvTimeoutError> {
+        assert!(max > 0, "a batch needs room for one message");
+        let batch = deadline::recv_until(
+            &self.shared.not_empty,
+            self.shared.lock(),
+            deadline::after(timeout),
+            |state| {
+                let count = max.min(state.queue.len());
+                (count > 0).then(|| state.queue.drain(..count).collect::<Vec<T>>())
+            },
+            |state| state.senders == 0,
+        )?;
+        if self.shared.capacity.is_some() {
+            self.shared.not_full.notify_all();
+        }
+        Ok(batch)
+    }
+
+    /// Iterates over messages until every sender is gone and the queue is drained
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv().ok())
+    }
+
+    /// Iterates over the messages that are queued right now
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_recv().ok())
+    }
+
+    /// Number of queued messages
+    pub fn len(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+
+    /// Whether no message is queued
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of queued messages, `None` if unbounded
+    pub fn capacity(&self) -> Option<usize> {
+        self.shared.capacity
+    }
+
+    fn take(&self, state: &mut State<T>) -> Option<T> {
+        let value = state.queue.pop_front()?;
+        if self.shared.capacity.is_some() {
+            self.shared.not_full.notify_one();
+        }
+        Some(value)
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().receivers += 1;
+        Receiver {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            drop(state);
+            // Senders blocked on a full queue would otherwise wait forever
+            self.shared.not_full.notify_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("len", &self.len())
+            .field("capacity", &self.shared.capacity)
+            .finish()
+    }
+}
+
+impl<T> TryRecv for Receiver<T> {
+    type Item = T;
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        Receiver::try_recv(self)
+    }
+
+    fn register(&self, waker: &Waker) -> bool {
+        self.shared.wakers.register(waker);
+        true
+    }
+
+    fn unregister(&self, waker: &Waker) {
+        self.shared.wakers.unregister(waker);
+    }
+}
+
+/// Owning iterator over the messages of a receiver
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn every_message_reaches_exactly_one_consumer() {
+        let (sender, receiver) = unbounded();
+        let consumers: Vec<_> = (0..3)
+            .map(|_| {
+                let receiver = receiver.clone();
+                thread::spawn(move || receiver.into_iter().collect::<Vec<i32>>())
+            })
+            .collect();
+        drop(receiver);
+        let producers: Vec<_> = (0..2)
+            .map(|producer| {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for value in 0..50 {
+                        sender.send(producer * 100 + value).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let mut received: Vec<i32> = consumers
+            .into_iter()
+            .flat_map(|consumer| consumer.join().unwrap())
+            .collect();
+        received.sort();
+        let expected: Vec<i32> = (0..50).chain(100..150).collect();
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn bounded_channel_blocks_when_full() {
+        let (sender, receiver) = bounded(2);
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        assert_eq!(sender.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(
+            sender.send_timeout(3, Duration::from_millis(10)),
+            Err(SendTimeoutError::Timeout(3))
+        );
+        let producer = thread::spawn(move || sender.send(3));
+        assert_eq!(receiver.recv(), Ok(1));
+        producer.join().unwrap().unwrap();
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn recv_timeout_and_try_recv() {
+        let (sender, receiver) = unbounded::<i32>();
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        sender.send(4).unwrap();
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok(4));
+        sender.send(5).unwrap();
+        assert_eq!(receiver.recv_timeout(Duration::MAX), Ok(5));
+        sender.send(6).unwrap();
+        assert_eq!(receiver.recv_batch(2, Duration::MAX), Ok(vec![6]));
+        drop(sender);
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(10)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn dropping_every_receiver_disconnects_senders() {
+        let (sender, receiver) = bounded(1);
+        let other = receiver.clone();
+        sender.send(1).unwrap();
+        let blocked = {
+            let sender = sender.clone();
+            thread::spawn(move || sender.send(2))
+        };
+        drop(receiver);
+        drop(other);
+        // The blocked sender wakes up and gets its value back
+        assert_eq!(blocked.join().unwrap(), Err(SendError(2)));
+        assert_eq!(sender.send(3), Err(SendError(3)));
+        assert_eq!(sender.try_send(4), Err(TrySendError::Disconnected(4)));
+    }
+
+    #[test]
+    fn batches_move_many_messages_per_call() {
+        let (sender, receiver) = unbounded();
+        assert_eq!(sender.send_batch(0..10), Ok(10));
+        assert_eq!(
+            receiver.recv_batch(4, Duration::from_secs(10)),
+            Ok(vec![0, 1, 2, 3])
+        );
+        assert_eq!(
+            receiver.recv_batch(100, Duration::from_secs(10)),
+            Ok((4..10).collect())
+        );
+        assert_eq!(
+            receiver.recv_batch(100, Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        drop(sender);
+        assert_eq!(
+            receiver.recv_batch(100, Duration::from_secs(10)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn bounded_batches_wait_for_room() {
+        let (sender, receiver) = bounded(4);
+        let producer = thread::spawn(move || sender.send_batch(0..100));
+        let mut received = Vec::new();
+        while let Ok(batch) = receiver.recv_batch(3, Duration::from_secs(10)) {
+            assert!(batch.len() <= 3);
+            received.extend(batch);
+        }
+        assert_eq!(producer.join().unwrap(), Ok(100));
+        assert_eq!(received, (0..100).collect::<Vec<_>>());
+
+        // Values that could not be queued are given back
+        let (sender, receiver) = bounded(2);
+        drop(receiver);
+        assert_eq!(
+            sender.send_batch(vec![1, 2, 3]),
+            Err(SendError(vec![1, 2, 3]))
+        );
+    }
+}
@@ -0,0 +1,374 @@
+//! The lock-free flavour, a linked list of nodes in the style of Dmitry Vyukov's
+//! intrusive MPSC queue.
+//!
+//! Producers push by swapping the `head` pointer to their new node and then
+//! linking the previous head to it, so a send is one atomic swap and one store
+//! whatever the contention. Only the single consumer walks the list from
+//! `tail`, which always points at an already consumed "stub" node. Between a
+//! producer's swap and its link the list is briefly cut; the consumer sees
+//! that as [`Pop::Inconsistent`] and retries.
+//!
+//! A receiver that finds the queue empty parks its thread. It announces that
+//! with the `parked` flag before looking at the queue one last time, and
+//! senders check the flag after pushing, so one of the two always sees the
+//! other: either the receiver finds the message or the sender unparks it.
+
+use std::cell::{Cell, UnsafeCell};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use crate::channel::select::{TryRecv, Waker, Wakers};
+use crate::deadline;
+
+struct Node<T> {
+    next: AtomicPtr<Node<T>>,
+    /// `None` in the stub node
+    value: Option<T>,
+}
+
+impl<T> Node<T> {
+    fn new(value: Option<T>) -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            next: AtomicPtr::new(ptr::null_mut()),
+            value,
+        }))
+    }
+}
+
+/// Result of one attempt to take a message
+enum Pop<T> {
+    Data(T),
+    Empty,
+    /// A producer has swapped `head` but not linked its node yet
+    Inconsistent,
+}
+
+struct Queue<T> {
+    /// Most recently pushed node, swapped by producers
+    head: AtomicPtr<Node<T>>,
+    /// Consumed node whose `next` is the oldest message; only the receiver touches it
+    tail: UnsafeCell<*mut Node<T>>,
+}
+
+impl<T> Queue<T> {
+    fn new() -> Queue<T> {
+        let stub = Node::new(None);
+        Queue {
+            head: AtomicPtr::new(stub),
+            tail: UnsafeCell::new(stub),
+        }
+    }
+
+    fn push(&self, value: T) {
+        let node = Node::new(Some(value));
+        // SeqCst pairs with the receiver's `parked` store, see `Receiver::recv_until`
+        let previous = self.head.swap(node, Ordering::SeqCst);
+        // SAFETY: `previous` stays allocated until the consumer moves past it,
+        // which it cannot do before this store links it to `node`
+        unsafe { (*previous).next.store(node, Ordering::Release) };
+    }
+
+    /// Takes the oldest message.
+    ///
+    /// # Safety
+    ///
+    /// Only one thread at a time may pop.
+    unsafe fn pop(&self) -> Pop<T> {
+        // SAFETY: the caller guarantees exclusive access to `tail`, and the node
+        // it points at is only freed here
+        unsafe {
+            let tail = *self.tail.get();
+            let next = (*tail).next.load(Ordering::Acquire);
+            if !next.is_null() {
+                *self.tail.get() = next;
+                let value = (*next).value.take().expect("only the stub node is empty");
+                drop(Box::from_raw(tail));
+                return Pop::Data(value);
+            }
+            if self.head.load(Ordering::SeqCst) == tail {
+                Pop::Empty
+            } else {
+                Pop::Inconsistent
+            }
+        }
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        let mut node = *self.tail.get_mut();
+        while !node.is_null() {
+            // SAFETY: nobody else can reach the list any more
+            let boxed = unsafe { Box::from_raw(node) };
+            node = boxed.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+struct Shared<T> {
+    queue: Queue<T>,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    /// Set while the receiver is about to park or parked
+    parked: AtomicBool,
+    /// Thread of the receiver, recorded before it parks
+    waiter: Mutex<Option<Thread>>,
+    /// Selects parked on the receiver, woken along with it
+    wakers: Wakers,
+}
+
+// SAFETY: the raw pointers only ever reach values of type `T` moved in by
+// senders and out by the single receiver, and `tail` is only used through the
+// receiver, which is not `Sync`
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    /// Unparks the receiver if it announced it may park, and wakes the selects on it
+    fn wake_receiver(&self) {
+        if self.parked.load(Ordering::SeqCst)
+            && let Some(thread) = self.waiter.lock().unwrap().as_ref()
+        {
+            thread.unpark();
+        }
+        self.wakers.wake_all();
+    }
+}
+
+/// Creates an unbounded channel, like `std::sync::mpsc::channel`
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: Queue::new(),
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+        parked: AtomicBool::new(false),
+        waiter: Mutex::new(None),
+        wakers: Wakers::default(),
+    });
+    let receiver = Receiver {
+        shared: Arc::clone(&shared),
+        _not_sync: PhantomData,
+    };
+    (Sender { shared }, receiver)
+}
+
+/// The sending half; clone it to get more producers
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Queues `value`, failing only if the receiver is gone
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        if !self.shared.receiver_alive.load(Ordering::Acquire) {
+            return Err(SendError(value));
+        }
+        self.shared.queue.push(value);
+        self.shared.wake_receiver();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.shared.wake_receiver();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+/// The receiving half; it can move to another thread but not be shared
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    /// Being `!Sync` is what makes the receiver the single consumer of the queue
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until a message arrives or every sender is gone
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.recv_until(None).map_err(|_| RecvError)
+    }
+
+    /// Returns a queued message without blocking
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        if let Some(value) = self.pop() {
+            return Ok(value);
+        }
+        // Senders push before they leave, so check the queue once more after
+        // seeing the last one go
+        if self.shared.senders.load(Ordering::SeqCst) == 0 {
+            return self.pop().ok_or(TryRecvError::Disconnected);
+        }
+        Err(TryRecvError::Empty)
+    }
+
+    /// Waits at most `timeout` for a message
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_until(deadline::after(timeout))
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        loop {
+            match self.try_recv() {
+                Ok(value) => return Ok(value),
+                Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+                Err(TryRecvError::Empty) => {}
+            }
+            let timeout = match deadline {
+                None => None,
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    Some(deadline - now)
+                }
+            };
+            *self.shared.waiter.lock().unwrap() = Some(thread::current());
+            self.shared.parked.store(true, Ordering::SeqCst);
+            // A sender that pushed before seeing the flag did not unpark us
+            let last_look = self.try_recv();
+            if !matches!(last_look, Err(TryRecvError::Empty)) {
+                self.shared.parked.store(false, Ordering::SeqCst);
+                return last_look.map_err(|_| RecvTimeoutError::Disconnected);
+            }
+            match timeout {
+                None => thread::park(),
+                Some(timeout) => thread::park_timeout(timeout),
+            }
+            self.shared.parked.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Takes the oldest message, waiting out a producer caught between its swap and its link
+    fn pop(&self) -> Option<T> {
+        loop {
+            // SAFETY: the receiver is not `Sync`, so only this thread pops
+            match unsafe { self.shared.queue.pop() } {
+                Pop::Data(value) => return Some(value),
+                Pop::Empty => return None,
+                Pop::Inconsistent => thread::yield_now(),
+            }
+        }
+    }
+
+    /// Iterates over messages until every sender is gone
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+
+    /// Iterates over the messages that are queued right now
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { receiver: self }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver_alive.store(false, Ordering::Release);
+        // Drop what is queued now; later messages go when the last sender does
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<T> TryRecv for Receiver<T> {
+    type Item = T;
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        Receiver::try_recv(self)
+    }
+
+    fn register(&self, waker: &Waker) -> bool {
+        self.shared.wakers.register(waker);
+        true
+    }
+
+    fn unregister(&self, waker: &Waker) {
+        self.shared.wakers.unregister(waker);
+    }
+}
+
+/// Blocking iterator over the messages of a borrowed receiver
+pub struct Iter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// Non-blocking iterator over the messages queued in a borrowed receiver
+pub struct TryIter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Iterator for TryIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// Owning blocking iterator over the messages of a receiver
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
@@ -0,0 +1,206 @@
+//! Two from-scratch implementations of the `std::sync::mpsc` channel.
+//!
+//! Both flavours have the std API: [`locked::channel`] and
+//! [`lockfree::channel`] return a cloneable `Sender` with `send` and a
+//! `Receiver` with `recv`, `try_recv`, `recv_timeout`, `iter`, `try_iter` and
+//! `IntoIterator`, and they use the std error types. Swapping
+//! `use std::sync::mpsc;` for `use crate::channel::mpsc::lockfree as mpsc;` is
+//! enough to run code against them.
+//!
+//! - [`locked`] keeps a `VecDeque` behind a `Mutex` and parks the receiver on a
+//!   `Condvar`.
+//! - [`lockfree`] is a linked list that producers append to with one atomic
+//!   swap; the receiver parks its thread when the list is empty.
+//!
+//! The tests run the scenarios of the `channel` demos against both flavours
+//! and against std, and expect the same observable behaviour.
+
+pub mod locked;
+pub mod lockfree;
+
+#[cfg(test)]
+mod tests {
+    /// Defines the channel scenarios in a module that uses `$flavour` as `mpsc`
+    macro_rules! scenarios {
+        ($name:ident, $($flavour:tt)+) => {
+            mod $name {
+                use std::collections::BTreeMap;
+                use std::sync::Arc;
+                use std::sync::mpsc::{RecvTimeoutError, SendError, TryRecvError};
+                use std::thread;
+                use std::time::Duration;
+
+                use crate::clock::{self, SharedClock, VirtualClock};
+                use $($flavour)+ as mpsc;
+
+                /// One message after a pause, like the `channel` demo
+                pub fn single() -> (String, Duration) {
+                    let clock: SharedClock = VirtualClock::shared();
+                    let (sender, receiver) = mpsc::channel();
+                    let sender_clock = Arc::clone(&clock);
+                    let sending = clock::spawn(&clock, move || {
+                        sender_clock.sleep(Duration::from_secs(2));
+                        sender.send("Hello, World!".to_string()).unwrap();
+                    });
+                    let receiving = thread::spawn(move || receiver.recv().unwrap());
+                    sending.join().unwrap();
+                    (receiving.join().unwrap(), clock.now())
+                }
+
+                /// Paced messages drained through `IntoIterator`, like `channel-iterator`
+                pub fn iterator() -> (Vec<String>, Duration) {
+                    let clock: SharedClock = VirtualClock::shared();
+                    let (sender, receiver) = mpsc::channel();
+                    let sender_clock = Arc::clone(&clock);
+                    let sending = clock::spawn(&clock, move || {
+                        for number in 0..5 {
+                            sender_clock.sleep(Duration::from_secs(2));
+                            sender.send(format!("message {}", number)).unwrap();
+                        }
+                    });
+                    let receiving = thread::spawn(move || receiver.into_iter().collect());
+                    sending.join().unwrap();
+                    (receiving.join().unwrap(), clock.now())
+                }
+
+                /// Cloned senders at different paces, like `channel-multi-sender`;
+                /// returns what the receiver got from each sender, in arrival order
+                pub fn multi_sender() -> (BTreeMap<usize, Vec<usize>>, Duration) {
+                    let clock: SharedClock = VirtualClock::shared();
+                    let (sender, receiver) = mpsc::channel();
+                    let setup = clock::participate(&clock);
+                    let senders: Vec<_> = (1..=2)
+                        .map(|number| {
+                            let sender = sender.clone();
+                            let sender_clock = Arc::clone(&clock);
+                            clock::spawn(&clock, move || {
+                                for sequence in 0..5 {
+                                    sender_clock.sleep(Duration::from_secs(number as u64));
+                                    sender.send((number, sequence)).unwrap();
+                                }
+                            })
+                        })
+                        .collect();
+                    drop(setup);
+                    drop(sender);
+                    let receiving = thread::spawn(move || {
+                        let mut received = BTreeMap::<usize, Vec<usize>>::new();
+                        for (number, sequence) in receiver.iter() {
+                            received.entry(number).or_default().push(sequence);
+                        }
+                        received
+                    });
+                    for handle in senders {
+                        handle.join().unwrap();
+                    }
+                    (receiving.join().unwrap(), clock.now())
+                }
+
+                /// Non-blocking and timed receives around a disconnect
+                pub fn timeouts_and_disconnect() -> Vec<String> {
+                    let (sender, receiver) = mpsc::channel::<i32>();
+                    let mut log = Vec::new();
+                    log.push(format!("{:?}", receiver.try_recv()));
+                    log.push(format!("{:?}", receiver.recv_timeout(Duration::from_millis(5))));
+                    let clone = sender.clone();
+                    sender.send(1).unwrap();
+                    clone.send(2).unwrap();
+                    drop(sender);
+                    log.push(format!("{:?}", receiver.recv_timeout(Duration::from_secs(10))));
+                    log.push(format!("{:?}", receiver.try_iter().collect::<Vec<_>>()));
+                    log.push(format!("{:?}", receiver.try_recv()));
+                    drop(clone);
+                    log.push(format!("{:?}", receiver.try_recv()));
+                    log.push(format!("{:?}", receiver.recv()));
+                    log.push(format!("{:?}", receiver.recv_timeout(Duration::from_secs(10))));
+                    assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+                    assert_eq!(
+                        receiver.recv_timeout(Duration::ZERO),
+                        Err(RecvTimeoutError::Disconnected)
+                    );
+                    log
+                }
+
+                /// A timeout too long for an `Instant` waits like `recv`
+                pub fn unbounded_timeout() -> Vec<String> {
+                    let (sender, receiver) = mpsc::channel();
+                    let sending = thread::spawn(move || {
+                        thread::sleep(Duration::from_millis(5));
+                        sender.send(1).unwrap();
+                    });
+                    let mut log = vec![format!("{:?}", receiver.recv_timeout(Duration::MAX))];
+                    sending.join().unwrap();
+                    log.push(format!("{:?}", receiver.recv_timeout(Duration::MAX)));
+                    log
+                }
+
+                /// Dropping the receiver discards what is queued and refuses new messages
+                pub fn receiver_dropped() -> (usize, Option<i32>) {
+                    let (sender, receiver) = mpsc::channel();
+                    let queued = Arc::new(1);
+                    sender.send(Arc::clone(&queued)).unwrap();
+                    drop(receiver);
+                    let refused = match sender.send(Arc::new(2)) {
+                        Err(SendError(value)) => Some(*value),
+                        Ok(()) => None,
+                    };
+                    (Arc::strong_count(&queued), refused)
+                }
+
+                /// Many producers racing; every producer's messages arrive once and in order
+                pub fn contended() -> BTreeMap<usize, usize> {
+                    let (sender, receiver) = mpsc::channel();
+                    let producers: Vec<_> = (0..4)
+                        .map(|producer| {
+                            let sender = sender.clone();
+                            thread::spawn(move || {
+                                for sequence in 0..5_000 {
+                                    sender.send((producer, sequence)).unwrap();
+                                }
+                            })
+                        })
+                        .collect();
+                    drop(sender);
+                    let mut next = BTreeMap::new();
+                    for (producer, sequence) in receiver {
+                        let expected = next.entry(producer).or_insert(0);
+                        assert_eq!(sequence, *expected, "producer {} out of order", producer);
+                        *expected += 1;
+                    }
+                    for producer in producers {
+                        producer.join().unwrap();
+                    }
+                    next
+                }
+            }
+        };
+    }
+
+    scenarios!(reference, std::sync::mpsc);
+    scenarios!(locked, crate::channel::mpsc::locked);
+    scenarios!(lockfree, crate::channel::mpsc::lockfree);
+
+    /// Runs a scenario against every flavour and compares the results with std
+    macro_rules! differential {
+        ($($scenario:ident),+ $(,)?) => {
+            $(
+                #[test]
+                fn $scenario() {
+                    let expected = reference::$scenario();
+                    assert_eq!(locked::$scenario(), expected, "locked flavour");
+                    assert_eq!(lockfree::$scenario(), expected, "lock-free flavour");
+                }
+            )+
+        };
+    }
+
+    differential!(
+        single,
+        iterator,
+        multi_sender,
+        timeouts_and_disconnect,
+        unbounded_timeout,
+        receiver_dropped,
+        contended,
+    );
+}
@@ -0,0 +1,281 @@
+//! A channel for exactly one value.
+//!
+//! Both halves are consumed by use: [`Sender::send`] takes the sender and the
+//! receiving methods take the receiver, so the type system rules out a second
+//! message. There is no queue, only a single slot behind a `Mutex` and a
+//! `Condvar` to wake the receiver. A sender dropped without sending is
+//! reported as [`RecvError`] instead of leaving the receiver blocked.
+
+use std::fmt;
+use std::sync::mpsc::SendError;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::deadline;
+
+enum Slot<T> {
+    /// Nothing sent yet, both halves alive
+    Empty,
+    Sent(T),
+    /// The value was taken by the receiver
+    Taken,
+    SenderDropped,
+    ReceiverDropped,
+}
+
+struct Shared<T> {
+    slot: Mutex<Slot<T>>,
+    /// Signalled when the slot leaves `Empty`
+    ready: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, Slot<T>> {
+        self.slot.lock().unwrap()
+    }
+}
+
+/// The sender was dropped without sending a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the sender was dropped without sending")
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+/// Why [`Receiver::try_recv`] did not return the value
+pub enum TryRecvError<T> {
+    /// Nothing was sent yet; the receiver is given back
+    Empty(Receiver<T>),
+    /// The sender was dropped without sending
+    Disconnected,
+}
+
+/// Why [`Receiver::recv_timeout`] did not return the value
+pub enum RecvTimeoutError<T> {
+    /// Nothing was sent in time; the receiver is given back
+    Timeout(Receiver<T>),
+    /// The sender was dropped without sending
+    Disconnected,
+}
+
+impl<T> fmt::Debug for TryRecvError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty(_) => f.write_str("Empty(..)"),
+            TryRecvError::Disconnected => f.write_str("Disconnected"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TryRecvError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty(_) => f.write_str("no value was sent yet"),
+            TryRecvError::Disconnected => RecvError.fmt(f),
+        }
+    }
+}
+
+impl<T> std::error::Error for TryRecvError<T> {}
+
+impl<T> fmt::Debug for RecvTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout(_) => f.write_str("Timeout(..)"),
+            RecvTimeoutError::Disconnected => f.write_str("Disconnected"),
+        }
+    }
+}
+
+impl<T> fmt::Display for RecvTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout(_) => f.write_str("timed out waiting for the value"),
+            RecvTimeoutError::Disconnected => RecvError.fmt(f),
+        }
+    }
+}
+
+impl<T> std::error::Error for RecvTimeoutError<T> {}
+
+/// Creates a oneshot channel
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        slot: Mutex::new(Slot::Empty),
+        ready: Condvar::new(),
+    });
+    let receiver = Receiver {
+        shared: Arc::clone(&shared),
+    };
+    (Sender { shared }, receiver)
+}
+
+/// The sending half, used up by [`Sender::send`]
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends the value, or gives it back if the receiver is gone
+    pub fn send(self, value: T) -> Result<(), SendError<T>> {
+        let mut slot = self.shared.lock();
+        if let Slot::ReceiverDropped = *slot {
+            return Err(SendError(value));
+        }
+        *slot = Slot::Sent(value);
+        drop(slot);
+        self.shared.ready.notify_one();
+        Ok(())
+    }
+
+    /// Whether the receiver was dropped, so sending would fail
+    pub fn is_closed(&self) -> bool {
+        matches!(*self.shared.lock(), Slot::ReceiverDropped)
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut slot = self.shared.lock();
+        if let Slot::Empty = *slot {
+            *slot = Slot::SenderDropped;
+            drop(slot);
+            self.shared.ready.notify_one();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+/// The receiving half, used up by receiving the value
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until the value is sent or the sender is dropped
+    pub fn recv(self) -> Result<T, RecvError> {
+        let (mut slot, _) = self.wait_until(None);
+        take(&mut slot).ok_or(RecvError)
+    }
+
+    /// Returns the value if it has been sent, without blocking
+    pub fn try_recv(self) -> Result<T, TryRecvError<T>> {
+        let mut slot = self.shared.lock();
+        if let Slot::Empty = *slot {
+            drop(slot);
+            return Err(TryRecvError::Empty(self));
+        }
+        take(&mut slot).ok_or(TryRecvError::Disconnected)
+    }
+
+    /// Waits at most `timeout` for the value
+    pub fn recv_timeout(self, timeout: Duration) -> Result<T, RecvTimeoutError<T>> {
+        let (mut slot, timed_out) = self.wait_until(deadline::after(timeout));
+        if timed_out {
+            drop(slot);
+            return Err(RecvTimeoutError::Timeout(self));
+        }
+        take(&mut slot).ok_or(RecvTimeoutError::Disconnected)
+    }
+
+    /// Waits until the slot is no longer `Empty`; `true` if `deadline` passed first
+    fn wait_until(&self, deadline: Option<Instant>) -> (MutexGuard<'_, Slot<T>>, bool) {
+        deadline::wait_while(&self.shared.ready, self.shared.lock(), deadline, |slot| {
+            matches!(slot, Slot::Empty)
+        })
+    }
+}
+
+/// Takes the value out of a slot that is no longer `Empty`
+fn take<T>(slot: &mut Slot<T>) -> Option<T> {
+    match std::mem::replace(slot, Slot::Taken) {
+        Slot::Sent(value) => Some(value),
+        _ => None,
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut slot = self.shared.lock();
+        if let Slot::Empty | Slot::Sent(_) = *slot {
+            // A value nobody will receive is dropped here
+            *slot = Slot::ReceiverDropped;
+        }
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn sends_one_value_across_threads() {
+        let (sender, receiver) = channel();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            sender.send("Hello, World!".to_string()).unwrap();
+        });
+        assert_eq!(receiver.recv().unwrap(), "Hello, World!");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn dropped_sender_is_reported() {
+        let (sender, receiver) = channel::<i32>();
+        drop(sender);
+        assert_eq!(receiver.recv(), Err(RecvError));
+
+        let (sender, receiver) = channel::<i32>();
+        thread::spawn(move || drop(sender)).join().unwrap();
+        assert!(matches!(
+            receiver.recv_timeout(Duration::from_secs(10)),
+            Err(RecvTimeoutError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn try_recv_and_timeout_give_the_receiver_back() {
+        let (sender, receiver) = channel();
+        let Err(TryRecvError::Empty(receiver)) = receiver.try_recv() else {
+            panic!("nothing was sent yet");
+        };
+        let Err(RecvTimeoutError::Timeout(receiver)) =
+            receiver.recv_timeout(Duration::from_millis(10))
+        else {
+            panic!("nothing was sent yet");
+        };
+        sender.send(5).unwrap();
+        assert_eq!(receiver.try_recv().unwrap(), 5);
+
+        let (sender, receiver) = channel();
+        sender.send(6).unwrap();
+        assert_eq!(receiver.recv_timeout(Duration::MAX).unwrap(), 6);
+    }
+
+    #[test]
+    fn send_fails_without_receiver() {
+        let (sender, receiver) = channel();
+        drop(receiver);
+        assert!(sender.is_closed());
+        assert_eq!(sender.send(3), Err(SendError(3)));
+    }
+}
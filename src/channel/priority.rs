@@ -0,0 +1,429 @@
+//! A channel delivering the most urgent message first.
+//!
+//! Every message carries a priority, higher meaning more urgent. Messages of
+//! the same priority keep their FIFO order, since each level is its own
+//! `VecDeque` in a `BTreeMap`. A message may also carry a deadline; once it
+//! has passed, the receiver skips the message and counts it as expired.
+//!
+//! Strict priorities can starve the low levels forever. An [`Aging`] policy
+//! raises the effective priority of a waiting message step by step, so every
+//! message is delivered after a bounded number of overtakes or a bounded time.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::mpsc::{RecvError, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::channel::select::{TryRecv, Waker, Wakers};
+use crate::deadline;
+
+/// How waiting messages gain priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Aging {
+    /// Priorities never change
+    #[default]
+    Never,
+    /// One level more for every this many messages delivered while waiting
+    Deliveries(u64),
+    /// One level more for every interval spent waiting
+    Interval(Duration),
+}
+
+struct Entry<T> {
+    value: T,
+    priority: u32,
+    /// Position in the order of all sends, to break ties in favour of older messages
+    sequence: u64,
+    /// Number of deliveries when the message was sent
+    delivered_before: u64,
+    sent_at: Instant,
+    deadline: Option<Instant>,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// Signalled when a message is queued or the last sender leaves
+    available: Condvar,
+    aging: Aging,
+    /// Selects parked on the receiver, woken like `available`
+    wakers: Wakers,
+}
+
+struct State<T> {
+    /// One FIFO queue per priority level
+    levels: BTreeMap<u32, VecDeque<Entry<T>>>,
+    len: usize,
+    /// Messages sent so far
+    sent: u64,
+    /// Messages handed to the receiver so far
+    delivered: u64,
+    /// Messages dropped because their deadline passed
+    expired: u64,
+    senders: usize,
+    receiver: bool,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap()
+    }
+
+    /// Priority of `entry` including what it gained while waiting
+    fn effective_priority(&self, entry: &Entry<T>, delivered: u64, now: Instant) -> u64 {
+        let boost = match self.aging {
+            Aging::Never => 0,
+            Aging::Deliveries(every) => (delivered - entry.delivered_before) / every.max(1),
+            Aging::Interval(every) => {
+                let waited = now.duration_since(entry.sent_at).as_nanos();
+                (waited / every.as_nanos().max(1)) as u64
+            }
+        };
+        entry.priority as u64 + boost
+    }
+
+    /// Takes the most urgent message that has not expired
+    fn take(&self, state: &mut State<T>) -> Option<T> {
+        let now = Instant::now();
+        loop {
+            // The head of each level waited longest, so it has the highest
+            // effective priority of its level. Ties go to the older message.
+            let level = if self.aging == Aging::Never {
+                *state.levels.keys().next_back()?
+            } else {
+                let delivered = state.delivered;
+                state
+                    .levels
+                    .iter()
+                    .map(|(level, queue)| {
+                        let head = queue.front().expect("empty levels are removed");
+                        let priority = self.effective_priority(head, delivered, now);
+                        (priority, Reverse(head.sequence), *level)
+                    })
+                    .max()?
+                    .2
+            };
+            let queue = state.levels.get_mut(&level).unwrap();
+            let entry = queue.pop_front().unwrap();
+            if queue.is_empty() {
+                state.levels.remove(&level);
+            }
+            state.len -= 1;
+            if entry.deadline.is_some_and(|deadline| deadline <= now) {
+                state.expired += 1;
+                continue;
+            }
+            state.delivered += 1;
+            return Some(entry.value);
+        }
+    }
+}
+
+/// Returned by [`Sender::send`] when the receiver is gone; carries the value back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a channel without receiver")
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+
+/// Creates a priority channel with strict priorities
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    with_aging(Aging::Never)
+}
+
+/// Creates a priority channel whose waiting messages age according to `aging`
+pub fn with_aging<T>(aging: Aging) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            levels: BTreeMap::new(),
+            len: 0,
+            sent: 0,
+            delivered: 0,
+            expired: 0,
+            senders: 1,
+            receiver: true,
+        }),
+        available: Condvar::new(),
+        aging,
+        wakers: Wakers::default(),
+    });
+    let receiver = Receiver {
+        shared: Arc::clone(&shared),
+    };
+    (Sender { shared }, receiver)
+}
+
+/// The sending half; clone it to get more producers
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Queues `value` with `priority`; higher values are delivered first
+    pub fn send(&self, value: T, priority: u32) -> Result<(), SendError<T>> {
+        self.push(value, priority, None)
+    }
+
+    /// Queues `value` with `priority`, to be dropped if still queued after `timeout`.
+    ///
+    /// A timeout too long for an `Instant` never expires.
+    pub fn send_with_deadline(
+        &self,
+        value: T,
+        priority: u32,
+        timeout: Duration,
+    ) -> Result<(), SendError<T>> {
+        self.push(value, priority, deadline::after(timeout))
+    }
+
+    fn push(&self, value: T, priority: u32, deadline: Option<Instant>) -> Result<(), SendError<T>> {
+        let mut state = self.shared.lock();
+        if !state.receiver {
+            return Err(SendError(value));
+        }
+        let entry = Entry {
+            value,
+            priority,
+            sequence: state.sent,
+            delivered_before: state.delivered,
+            sent_at: Instant::now(),
+            deadline,
+        };
+        state.levels.entry(priority).or_default().push_back(entry);
+        state.len += 1;
+        state.sent += 1;
+        drop(state);
+        self.shared.available.notify_one();
+        self.shared.wakers.wake_all();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.available.notify_all();
+            self.shared.wakers.wake_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("aging", &self.shared.aging)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The receiving half
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until a message is available or every sender is gone
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.recv_until(None).map_err(|_| RecvError)
+    }
+
+    /// Returns the most urgent queued message without blocking
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match self.shared.take(&mut state) {
+            Some(value) => Ok(value),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Waits at most `timeout` for a message
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_until(deadline::after(timeout))
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        deadline::recv_until(
+            &self.shared.available,
+            self.shared.lock(),
+            deadline,
+            |state| self.shared.take(state),
+            |state| state.senders == 0,
+        )
+    }
+
+    /// Iterates over messages until every sender is gone and the queue is drained
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv().ok())
+    }
+
+    /// Iterates over the messages that are queued right now
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_recv().ok())
+    }
+
+    /// Number of queued messages, including expired ones not skipped yet
+    pub fn len(&self) -> usize {
+        self.shared.lock().len
+    }
+
+    /// Whether no message is queued
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Messages skipped so far because their deadline had passed
+    pub fn expired(&self) -> u64 {
+        self.shared.lock().expired
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.lock().receiver = false;
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("len", &self.len())
+            .field("expired", &self.expired())
+            .finish()
+    }
+}
+
+impl<T> TryRecv for Receiver<T> {
+    type Item = T;
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        Receiver::try_recv(self)
+    }
+
+    fn register(&self, waker: &Waker) -> bool {
+        self.shared.wakers.register(waker);
+        true
+    }
+
+    fn unregister(&self, waker: &Waker) {
+        self.shared.wakers.unregister(waker);
+    }
+}
+
+/// Owning iterator over the messages of a receiver
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn highest_priority_first_and_fifo_within_a_level() {
+        let (sender, receiver) = channel();
+        sender.send("low 1", 1).unwrap();
+        sender.send("high 1", 5).unwrap();
+        sender.send("low 2", 1).unwrap();
+        sender.send("high 2", 5).unwrap();
+        sender.send("middle", 3).unwrap();
+        drop(sender);
+        assert_eq!(
+            receiver.into_iter().collect::<Vec<_>>(),
+            vec!["high 1", "high 2", "middle", "low 1", "low 2"]
+        );
+    }
+
+    #[test]
+    fn expired_messages_are_skipped_and_counted() {
+        let (sender, receiver) = channel();
+        sender
+            .send_with_deadline("stale", 9, Duration::from_millis(1))
+            .unwrap();
+        sender
+            .send_with_deadline("fresh", 1, Duration::from_secs(60))
+            .unwrap();
+        sender
+            .send_with_deadline("forever", 0, Duration::MAX)
+            .unwrap();
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(receiver.try_recv(), Ok("fresh"));
+        assert_eq!(receiver.expired(), 1);
+        assert_eq!(receiver.recv_timeout(Duration::MAX), Ok("forever"));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    /// Sends one urgent message before every receive, as a busy producer would
+    fn overtaken_low_message_position(aging: Aging) -> Option<usize> {
+        let (sender, receiver) = with_aging(aging);
+        sender.send("low", 0).unwrap();
+        (0..10).position(|_| {
+            sender.send("high", 2).unwrap();
+            receiver.try_recv() == Ok("low")
+        })
+    }
+
+    #[test]
+    fn aging_bounds_starvation() {
+        // Two levels behind, gaining one level per two deliveries: after four
+        // overtakes the low message ties with the new one and wins as the older
+        assert_eq!(
+            overtaken_low_message_position(Aging::Deliveries(2)),
+            Some(4)
+        );
+        assert_eq!(overtaken_low_message_position(Aging::Never), None);
+    }
+
+    #[test]
+    fn disconnects_like_mpsc() {
+        let (sender, receiver) = channel::<i32>();
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_millis(5)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        let producer = thread::spawn(move || sender.send(1, 0));
+        producer.join().unwrap().unwrap();
+        assert_eq!(receiver.recv(), Ok(1));
+        assert_eq!(receiver.recv(), Err(RecvError));
+
+        let (sender, receiver) = channel();
+        drop(receiver);
+        assert_eq!(sender.send(2, 0), Err(SendError(2)));
+    }
+}
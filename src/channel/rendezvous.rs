@@ -0,0 +1,491 @@
+//! Synchronous hand-off: a zero-capacity channel and an [`Exchanger`].
+//!
+//! A rendezvous [`Sender::send`] does not return until a receiver has taken
+//! the value, so both threads are at the same point of their protocol when it
+//! completes. Senders take turns offering their value in a single slot. An
+//! [`Exchanger`] goes one step further: two threads meet and each leaves with
+//! the value the other one brought.
+
+use std::fmt;
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::channel::select::{TryRecv, Waker, Wakers};
+use crate::deadline;
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// Signalled on every offer, take, withdrawal and disconnect
+    changed: Condvar,
+    /// Selects parked on the receiver, woken on every offer and the last disconnect
+    wakers: Wakers,
+}
+
+struct State<T> {
+    /// The value currently offered by one sender
+    slot: Option<T>,
+    /// Offers made so far, minus the ones withdrawn
+    offered: u64,
+    /// Offers taken by the receiver so far
+    taken: u64,
+    senders: usize,
+    receiver: bool,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap()
+    }
+}
+
+/// Why [`Sender::send_timeout`] did not hand the value over; carries it back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendTimeoutError<T> {
+    /// No receiver took the value in time
+    Timeout(T),
+    /// The receiver was dropped
+    Disconnected(T),
+}
+
+impl<T> fmt::Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => f.write_str("timed out waiting for a receiver"),
+            SendTimeoutError::Disconnected(_) => {
+                f.write_str("sending on a channel without receiver")
+            }
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendTimeoutError<T> {}
+
+/// Creates a rendezvous channel
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            slot: None,
+            offered: 0,
+            taken: 0,
+            senders: 1,
+            receiver: true,
+        }),
+        changed: Condvar::new(),
+        wakers: Wakers::default(),
+    });
+    let receiver = Receiver {
+        shared: Arc::clone(&shared),
+    };
+    (Sender { shared }, receiver)
+}
+
+/// The sending half; clone it to get more producers
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Blocks until the receiver has taken `value`
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.send_until(value, None).map_err(|error| match error {
+            SendTimeoutError::Disconnected(value) => SendError(value),
+            SendTimeoutError::Timeout(_) => unreachable!("no deadline was set"),
+        })
+    }
+
+    /// Blocks at most `timeout` for the receiver to take `value`
+    pub fn send_timeout(&self, value: T, timeout: Duration) -> Result<(), SendTimeoutError<T>> {
+        self.send_until(value, deadline::after(timeout))
+    }
+
+    fn send_until(&self, value: T, deadline: Option<Instant>) -> Result<(), SendTimeoutError<T>> {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        // Wait for the other senders' offers to be taken
+        while state.slot.is_some() && state.receiver {
+            match deadline::wait(&shared.changed, state, deadline) {
+                Ok(next) => state = next,
+                Err(_) => return Err(SendTimeoutError::Timeout(value)),
+            }
+        }
+        if !state.receiver {
+            return Err(SendTimeoutError::Disconnected(value));
+        }
+        state.slot = Some(value);
+        state.offered += 1;
+        let ticket = state.offered;
+        shared.changed.notify_all();
+        shared.wakers.wake_all();
+        // Wait for the receiver to take the offer
+        while state.taken < ticket && state.receiver {
+            match deadline::wait(&shared.changed, state, deadline) {
+                Ok(next) => state = next,
+                Err(mut state) => {
+                    if state.taken >= ticket {
+                        // Taken just as the timeout expired
+                        return Ok(());
+                    }
+                    let value = withdraw(&mut state);
+                    shared.changed.notify_all();
+                    return Err(SendTimeoutError::Timeout(value));
+                }
+            }
+        }
+        if state.taken < ticket {
+            let value = withdraw(&mut state);
+            return Err(SendTimeoutError::Disconnected(value));
+        }
+        Ok(())
+    }
+}
+
+/// Takes back the offer in the slot, which must be the caller's own
+fn withdraw<T>(state: &mut State<T>) -> T {
+    state.offered -= 1;
+    state
+        .slot
+        .take()
+        .expect("an offer that was not taken is in the slot")
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.changed.notify_all();
+            self.shared.wakers.wake_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+/// The receiving half
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until a sender offers a value or every sender is gone
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.recv_until(None).map_err(|_| RecvError)
+    }
+
+    /// Waits at most `timeout` for a sender
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_until(deadline::after(timeout))
+    }
+
+    /// Takes a value only if a sender is offering one right now
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match self.take(&mut state) {
+            Some(value) => Ok(value),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Iterates over values until every sender is gone
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv().ok())
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        deadline::recv_until(
+            &self.shared.changed,
+            self.shared.lock(),
+            deadline,
+            |state| self.take(state),
+            |state| state.senders == 0,
+        )
+    }
+
+    fn take(&self, state: &mut State<T>) -> Option<T> {
+        let value = state.slot.take()?;
+        state.taken += 1;
+        // Release the sender and let the next one offer
+        self.shared.changed.notify_all();
+        Some(value)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.lock().receiver = false;
+        self.shared.changed.notify_all();
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<T> TryRecv for Receiver<T> {
+    type Item = T;
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        Receiver::try_recv(self)
+    }
+
+    fn register(&self, waker: &Waker) -> bool {
+        self.shared.wakers.register(waker);
+        true
+    }
+
+    fn unregister(&self, waker: &Waker) {
+        self.shared.wakers.unregister(waker);
+    }
+}
+
+/// Owning iterator over the values of a receiver
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+/// A meeting point where pairs of threads swap values.
+///
+/// Share it through an `Arc`. The first thread of a pair waits in
+/// [`Exchanger::exchange`]; the second one takes its value, leaves its own and
+/// both return. Later threads wait until the pair has finished.
+pub struct Exchanger<T> {
+    state: Mutex<Meeting<T>>,
+    changed: Condvar,
+}
+
+struct Meeting<T> {
+    /// Value of the thread waiting for a partner
+    waiting: Option<T>,
+    /// Value left by the partner, not collected yet
+    reply: Option<T>,
+    /// Bumped whenever a pair is complete
+    generation: u64,
+}
+
+impl<T> Exchanger<T> {
+    /// Creates an exchanger nobody is waiting at
+    pub fn new() -> Self {
+        Exchanger {
+            state: Mutex::new(Meeting {
+                waiting: None,
+                reply: None,
+                generation: 0,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Waits for a partner and returns the value it brought
+    pub fn exchange(&self, value: T) -> T {
+        match self.exchange_until(value, None) {
+            Ok(value) => value,
+            Err(_) => unreachable!("no deadline was set"),
+        }
+    }
+
+    /// Waits at most `timeout` for a partner; gives `value` back if none came
+    pub fn exchange_timeout(&self, value: T, timeout: Duration) -> Result<T, T> {
+        self.exchange_until(value, deadline::after(timeout))
+    }
+
+    fn exchange_until(&self, value: T, deadline: Option<Instant>) -> Result<T, T> {
+        let mut state = self.state.lock().unwrap();
+        // The previous pair is still collecting its reply
+        while state.reply.is_some() {
+            match deadline::wait(&self.changed, state, deadline) {
+                Ok(next) => state = next,
+                Err(_) => return Err(value),
+            }
+        }
+        if let Some(partner) = state.waiting.take() {
+            // Second of the pair: swap and wake the first
+            state.reply = Some(value);
+            state.generation += 1;
+            self.changed.notify_all();
+            return Ok(partner);
+        }
+        // First of the pair: wait for a partner to complete it
+        state.waiting = Some(value);
+        let generation = state.generation;
+        while state.generation == generation {
+            match deadline::wait(&self.changed, state, deadline) {
+                Ok(next) => state = next,
+                Err(mut state) => {
+                    if state.generation == generation {
+                        let value = state.waiting.take().expect("nobody took the value");
+                        return Err(value);
+                    }
+                    return Ok(self.collect(&mut state));
+                }
+            }
+        }
+        Ok(self.collect(&mut state))
+    }
+
+    fn collect(&self, state: &mut Meeting<T>) -> T {
+        let reply = state.reply.take().expect("the partner left a reply");
+        // Let the next pair in
+        self.changed.notify_all();
+        reply
+    }
+}
+
+impl<T> Default for Exchanger<T> {
+    fn default() -> Self {
+        Exchanger::new()
+    }
+}
+
+impl<T> fmt::Debug for Exchanger<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Exchanger").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    #[test]
+    fn send_waits_for_the_receiver() {
+        let (sender, receiver) = channel();
+        let delivered = Arc::new(AtomicBool::new(false));
+        let producer = {
+            let delivered = Arc::clone(&delivered);
+            thread::spawn(move || {
+                sender.send(1).unwrap();
+                delivered.store(true, Ordering::SeqCst);
+            })
+        };
+        thread::sleep(Duration::from_millis(20));
+        // Zero capacity: nothing was taken, so the sender is still blocked
+        assert!(!delivered.load(Ordering::SeqCst));
+        assert_eq!(receiver.recv(), Ok(1));
+        producer.join().unwrap();
+        assert!(delivered.load(Ordering::SeqCst));
+        assert_eq!(receiver.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn several_senders_take_turns() {
+        let (sender, receiver) = channel();
+        let producers: Vec<_> = (0..3)
+            .map(|id| {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for value in 0..10 {
+                        sender.send(id * 10 + value).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+        let mut received: Vec<i32> = receiver.into_iter().collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        received.sort();
+        assert_eq!(received, (0..30).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn timeouts_withdraw_the_offer() {
+        let (sender, receiver) = channel();
+        assert_eq!(
+            sender.send_timeout(1, Duration::from_millis(10)),
+            Err(SendTimeoutError::Timeout(1))
+        );
+        // The withdrawn value is not delivered later
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        drop(receiver);
+        assert_eq!(sender.send(2), Err(SendError(2)));
+
+        // Too long for an `Instant`: waits like the untimed calls
+        let (sender, receiver) = channel();
+        let producer = thread::spawn(move || sender.send_timeout(3, Duration::MAX));
+        assert_eq!(receiver.recv_timeout(Duration::MAX), Ok(3));
+        assert_eq!(producer.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn exchanger_swaps_values_in_lock_step() {
+        let exchanger = Arc::new(Exchanger::new());
+        let partner = {
+            let exchanger = Arc::clone(&exchanger);
+            thread::spawn(move || {
+                (0..5)
+                    .map(|i| exchanger.exchange(i * 10))
+                    .collect::<Vec<_>>()
+            })
+        };
+        let received: Vec<_> = (0..5).map(|i| exchanger.exchange(i)).collect();
+        assert_eq!(received, vec![0, 10, 20, 30, 40]);
+        assert_eq!(partner.join().unwrap(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn exchange_timeout_gives_the_value_back() {
+        let exchanger = Exchanger::new();
+        assert_eq!(
+            exchanger.exchange_timeout("alone", Duration::from_millis(10)),
+            Err("alone")
+        );
+        // Nothing was left behind for the next thread
+        assert_eq!(
+            exchanger.exchange_timeout("again", Duration::from_millis(10)),
+            Err("again")
+        );
+
+        let exchanger = Arc::new(Exchanger::new());
+        let partner = {
+            let exchanger = Arc::clone(&exchanger);
+            thread::spawn(move || exchanger.exchange_timeout("left", Duration::MAX))
+        };
+        assert_eq!(
+            exchanger.exchange_timeout("right", Duration::MAX),
+            Ok("left")
+        );
+        assert_eq!(partner.join().unwrap(), Ok("right"));
+    }
+}
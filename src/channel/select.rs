@@ -0,0 +1,607 @@
+//! Waiting on several receivers at once.
+//!
+//! [`Select`] polls every arm with `try_recv`, starting each round at a random
+//! arm so that a channel that is always ready cannot starve the others. When
+//! nothing is ready it registers a [`Waker`] with every receiver and parks
+//! until one of them signals a sent value or a disconnect.
+//!
+//! The channels of this crate all signal their wakers. `std::sync::mpsc`
+//! cannot, so a select with a std receiver among its arms falls back to
+//! polling and backs off between rounds: a few spins, then yields, then sleeps
+//! of up to 1 ms. A value on a std receiver may then be seen up to 1 ms late,
+//! and the select keeps waking up about a thousand times a second until an
+//! arm fires; a value on a crate channel still ends the sleep right away.
+//!
+//! The [`select!`](crate::select) macro is sugar over the builder:
+//!
+//! ```
+//! use std::sync::mpsc;
+//! use std::time::Duration;
+//! use rust_concurrency::select;
+//!
+//! let (numbers, number_receiver) = mpsc::channel();
+//! let (_words, word_receiver) = mpsc::channel::<String>();
+//! numbers.send(7).unwrap();
+//!
+//! let picked = select! {
+//!     recv(number_receiver) -> number => format!("number {:?}", number),
+//!     recv(word_receiver) -> word => format!("word {:?}", word),
+//!     timeout(Duration::from_secs(1)) => "nothing".to_string(),
+//! };
+//! assert_eq!(picked, "number Ok(7)");
+//! ```
+
+use std::cell::Cell;
+use std::fmt;
+use std::hint;
+use std::ptr;
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::deadline;
+
+/// A receiver that can be polled without blocking
+pub trait TryRecv {
+    /// Type of the received values
+    type Item;
+
+    /// Takes a queued value, reporting an empty or disconnected channel
+    fn try_recv(&self) -> Result<Self::Item, TryRecvError>;
+
+    /// Asks the channel to wake `waker` whenever a value is sent or the channel disconnects.
+    ///
+    /// Returns `false` if the channel cannot, in which case a [`Select`]
+    /// polls it instead of parking.
+    fn register(&self, _waker: &Waker) -> bool {
+        false
+    }
+
+    /// Stops waking a waker passed to [`register`](TryRecv::register)
+    fn unregister(&self, _waker: &Waker) {}
+}
+
+impl<T> TryRecv for mpsc::Receiver<T> {
+    type Item = T;
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        mpsc::Receiver::try_recv(self)
+    }
+}
+
+/// Wakes a parked [`Select`] when one of its receivers may be ready
+#[derive(Clone)]
+pub struct Waker {
+    signal: Arc<Signal>,
+}
+
+struct Signal {
+    woken: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Waker {
+    fn new() -> Self {
+        Waker {
+            signal: Arc::new(Signal {
+                woken: Mutex::new(false),
+                condvar: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Wakes the select, or makes its next park return at once if it is not parked yet
+    pub fn wake(&self) {
+        *self.signal.woken.lock().unwrap() = true;
+        self.signal.condvar.notify_one();
+    }
+
+    /// Parks until woken or until `deadline`, consuming the wakeup
+    fn park(&self, deadline: Option<Instant>) {
+        let woken = self.signal.woken.lock().unwrap();
+        let (mut woken, _) =
+            deadline::wait_while(&self.signal.condvar, woken, deadline, |woken| !*woken);
+        *woken = false;
+    }
+}
+
+impl fmt::Debug for Waker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Waker").finish_non_exhaustive()
+    }
+}
+
+/// The wakers registered with one channel
+///
+/// Senders call [`wake_all`](Wakers::wake_all) after every send, so it only
+/// takes the lock while a select is registered. Wakers are held weakly: a
+/// select that unwinds out of a panicking handler without unregistering is
+/// dropped on the next wake.
+#[derive(Default)]
+pub(crate) struct Wakers {
+    registered: AtomicUsize,
+    wakers: Mutex<Vec<Weak<Signal>>>,
+}
+
+impl Wakers {
+    fn lock(&self) -> MutexGuard<'_, Vec<Weak<Signal>>> {
+        self.wakers.lock().unwrap()
+    }
+
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut wakers = self.lock();
+        wakers.push(Arc::downgrade(&waker.signal));
+        self.registered.store(wakers.len(), Ordering::SeqCst);
+        drop(wakers);
+        // Pairs with the fence in `wake_all`: either the select's next poll
+        // sees the value, or the sender sees the registration
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    pub(crate) fn unregister(&self, waker: &Waker) {
+        let mut wakers = self.lock();
+        let signal = Arc::as_ptr(&waker.signal);
+        wakers.retain(|other| other.strong_count() > 0 && !ptr::eq(other.as_ptr(), signal));
+        self.registered.store(wakers.len(), Ordering::SeqCst);
+    }
+
+    /// Wakes every registered select; call it after a send or a disconnect
+    pub(crate) fn wake_all(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.registered.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut wakers = self.lock();
+        wakers.retain(|signal| match signal.upgrade() {
+            Some(signal) => {
+                Waker { signal }.wake();
+                true
+            }
+            None => false,
+        });
+        self.registered.store(wakers.len(), Ordering::SeqCst);
+    }
+}
+
+/// The wakers of a channel built on `std::sync::mpsc`, as held by a sender
+///
+/// Dropping it wakes them, so a sender declares it after its `mpsc::Sender`:
+/// fields drop in order, and a woken select then sees the disconnect.
+#[derive(Clone)]
+pub(crate) struct SenderWakers(pub(crate) Arc<Wakers>);
+
+impl Drop for SenderWakers {
+    fn drop(&mut self) {
+        self.0.wake_all();
+    }
+}
+
+/// The arm of a [`Select`] that fired
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fired {
+    /// The receive arm registered at this position (counting receive arms only)
+    Recv(usize),
+    /// The timeout elapsed
+    Timeout,
+    /// Nothing was ready and a default arm was present
+    Default,
+}
+
+/// Which arm fired and what its closure returned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selected<R> {
+    pub fired: Fired,
+    pub value: R,
+}
+
+/// A receive arm with its handler, type-erased over the receiver
+trait Arm<R> {
+    /// Fires the arm if its receiver has a value or is disconnected
+    fn poll(&mut self) -> Option<R>;
+
+    /// Registers `waker` with the receiver, `false` if it must be polled
+    fn register(&self, waker: &Waker) -> bool;
+
+    fn unregister(&self, waker: &Waker);
+}
+
+struct RecvArm<'a, C, F> {
+    receiver: &'a C,
+    handler: Option<F>,
+}
+
+impl<C, F, R> Arm<R> for RecvArm<'_, C, F>
+where
+    C: TryRecv,
+    F: FnOnce(Result<C::Item, RecvError>) -> R,
+{
+    fn poll(&mut self) -> Option<R> {
+        let result = match self.receiver.try_recv() {
+            Ok(value) => Ok(value),
+            Err(TryRecvError::Disconnected) => Err(RecvError),
+            Err(TryRecvError::Empty) => return None,
+        };
+        let handler = self.handler.take().expect("an arm fires at most once");
+        Some(handler(result))
+    }
+
+    fn register(&self, waker: &Waker) -> bool {
+        self.receiver.register(waker)
+    }
+
+    fn unregister(&self, waker: &Waker) {
+        self.receiver.unregister(waker);
+    }
+}
+
+/// Builder waiting for the first of several receivers, a timeout or a default
+pub struct Select<'a, R> {
+    arms: Vec<Box<dyn Arm<R> + 'a>>,
+    timeout: Option<(Duration, Box<dyn FnOnce() -> R + 'a>)>,
+    default: Option<Box<dyn FnOnce() -> R + 'a>>,
+}
+
+impl<'a, R> Select<'a, R> {
+    /// Creates a select without arms
+    pub fn new() -> Self {
+        Select {
+            arms: Vec::new(),
+            timeout: None,
+            default: None,
+        }
+    }
+
+    /// Adds an arm firing when `receiver` has a value (`Ok`) or is disconnected (`Err`)
+    pub fn recv<C, F>(mut self, receiver: &'a C, handler: F) -> Self
+    where
+        C: TryRecv,
+        F: FnOnce(Result<C::Item, RecvError>) -> R + 'a,
+    {
+        self.arms.push(Box::new(RecvArm {
+            receiver,
+            handler: Some(handler),
+        }));
+        self
+    }
+
+    /// Adds an arm firing when no receiver was ready within `timeout`
+    pub fn timeout<F>(mut self, timeout: Duration, handler: F) -> Self
+    where
+        F: FnOnce() -> R + 'a,
+    {
+        self.timeout = Some((timeout, Box::new(handler)));
+        self
+    }
+
+    /// Adds an arm firing immediately when no receiver is ready, making the select non-blocking
+    pub fn default<F>(mut self, handler: F) -> Self
+    where
+        F: FnOnce() -> R + 'a,
+    {
+        self.default = Some(Box::new(handler));
+        self
+    }
+
+    /// Blocks until one arm fires and runs its handler.
+    ///
+    /// Parks while nothing is ready. With a `std::sync::mpsc` receiver among
+    /// the arms it polls instead, so that arm may fire up to 1 ms after its
+    /// value was sent; see the module documentation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no arm at all, since it would block forever.
+    pub fn wait(mut self) -> Selected<R> {
+        assert!(
+            !self.arms.is_empty() || self.timeout.is_some() || self.default.is_some(),
+            "select needs at least one arm"
+        );
+        // A timeout too far away to reach never fires
+        let deadline = self
+            .timeout
+            .as_ref()
+            .and_then(|(timeout, _)| deadline::after(*timeout));
+        if let Some(default) = self.default.take() {
+            return self.poll_round().unwrap_or_else(|| Selected {
+                fired: Fired::Default,
+                value: default(),
+            });
+        }
+        let waker = Waker::new();
+        // Every arm registers, even after one of them refused
+        let parks = self
+            .arms
+            .iter()
+            .fold(true, |parks, arm| arm.register(&waker) & parks);
+        let selected = self.wait_with(&waker, parks, deadline);
+        for arm in &self.arms {
+            arm.unregister(&waker);
+        }
+        selected
+    }
+
+    /// Polls until an arm fires, parking on `waker` if every arm signals it
+    fn wait_with(&mut self, waker: &Waker, parks: bool, deadline: Option<Instant>) -> Selected<R> {
+        let mut backoff = Backoff::new();
+        loop {
+            if let Some(selected) = self.poll_round() {
+                return selected;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                let (_, handler) = self.timeout.take().unwrap();
+                return Selected {
+                    fired: Fired::Timeout,
+                    value: handler(),
+                };
+            }
+            if parks {
+                waker.park(deadline);
+            } else {
+                backoff.wait(waker, deadline);
+            }
+        }
+    }
+
+    /// Polls every receive arm once, starting at a random one
+    fn poll_round(&mut self) -> Option<Selected<R>> {
+        let count = self.arms.len();
+        if count == 0 {
+            return None;
+        }
+        let start = random_below(count);
+        for offset in 0..count {
+            let index = (start + offset) % count;
+            if let Some(value) = self.arms[index].poll() {
+                return Some(Selected {
+                    fired: Fired::Recv(index),
+                    value,
+                });
+            }
+        }
+        None
+    }
+}
+
+impl<R> Default for Select<'_, R> {
+    fn default() -> Self {
+        Select::new()
+    }
+}
+
+/// Growing pause between polling rounds
+struct Backoff {
+    round: u32,
+}
+
+impl Backoff {
+    const SPIN_ROUNDS: u32 = 6;
+    const YIELD_ROUNDS: u32 = 10;
+    const MAX_SLEEP: Duration = Duration::from_millis(1);
+
+    fn new() -> Self {
+        Backoff { round: 0 }
+    }
+
+    /// Pauses a little longer than last time, never beyond `deadline`.
+    ///
+    /// Sleeps end early when `waker` is woken by a channel that signals.
+    fn wait(&mut self, waker: &Waker, deadline: Option<Instant>) {
+        if self.round < Self::SPIN_ROUNDS {
+            for _ in 0..1 << self.round {
+                hint::spin_loop();
+            }
+        } else if self.round < Self::SPIN_ROUNDS + Self::YIELD_ROUNDS {
+            thread::yield_now();
+        } else {
+            let exponent = (self.round - Self::SPIN_ROUNDS - Self::YIELD_ROUNDS).min(5);
+            let pause = Duration::from_micros(32 << exponent).min(Self::MAX_SLEEP);
+            let until = [deadline::after(pause), deadline]
+                .into_iter()
+                .flatten()
+                .min();
+            waker.park(until);
+        }
+        self.round = self.round.saturating_add(1);
+    }
+}
+
+/// A per-thread xorshift generator choosing where each polling round starts
+fn random_below(bound: usize) -> usize {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new({
+            // Seed from the address of a stack slot and the current time
+            let local = 0u8;
+            let address = &local as *const u8 as u64;
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+            (address ^ nanos.rotate_left(32)) | 1
+        });
+    }
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        (x % bound as u64) as usize
+    })
+}
+
+/// Waits on several receivers, a timeout and a default arm.
+///
+/// ```text
+/// select! {
+///     recv(receiver) -> result => expression,
+///     recv(other) -> result => expression,
+///     timeout(duration) => expression,
+///     default => expression,
+/// }
+/// ```
+///
+/// `result` is a `Result<T, RecvError>` pattern: `Err` means the channel is
+/// disconnected. Arms are separated by commas and every arm body becomes a
+/// closure, so it must produce the value of the whole `select!` rather than
+/// `return` or `break` out of the surrounding code.
+#[macro_export]
+macro_rules! select {
+    (@build $select:expr ;) => {
+        $select.wait().value
+    };
+    (@build $select:expr ; recv($receiver:expr) -> $result:pat_param => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::select!(@build $select.recv(&$receiver, |$result| $body) ; $($($rest)*)?)
+    };
+    (@build $select:expr ; timeout($timeout:expr) => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::select!(@build $select.timeout($timeout, || $body) ; $($($rest)*)?)
+    };
+    (@build $select:expr ; default => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::select!(@build $select.default(|| $body) ; $($($rest)*)?)
+    };
+    ($($arms:tt)+) => {
+        $crate::select!(@build $crate::channel::select::Select::new() ; $($arms)+)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fires_the_ready_arm() {
+        let (_first, first_receiver) = mpsc::channel::<i32>();
+        let (second, second_receiver) = mpsc::channel::<&str>();
+        second.send("ready").unwrap();
+        let selected = Select::new()
+            .recv(&first_receiver, |value| format!("{:?}", value))
+            .recv(&second_receiver, |value| format!("{:?}", value))
+            .wait();
+        assert_eq!(selected.fired, Fired::Recv(1));
+        assert_eq!(selected.value, "Ok(\"ready\")");
+    }
+
+    #[test]
+    fn blocks_until_a_value_arrives() {
+        let (sender, receiver) = mpsc::channel();
+        let (_other, other_receiver) = mpsc::channel::<i32>();
+        let producer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            sender.send(5).unwrap();
+        });
+        let value = select! {
+            recv(other_receiver) -> _value => 0,
+            recv(receiver) -> value => value.unwrap(),
+        };
+        assert_eq!(value, 5);
+        producer.join().unwrap();
+    }
+
+    #[test]
+    fn timeout_and_default_arms() {
+        let (_sender, receiver) = mpsc::channel::<i32>();
+        let started = Instant::now();
+        let selected = Select::new()
+            .recv(&receiver, |_| "value")
+            .timeout(Duration::from_millis(30), || "timeout")
+            .wait();
+        assert_eq!(selected.fired, Fired::Timeout);
+        assert!(started.elapsed() >= Duration::from_millis(30));
+
+        let value = select! {
+            recv(receiver) -> _value => "value",
+            default => "default",
+        };
+        assert_eq!(value, "default");
+
+        // Too far away for an `Instant`, so it never fires
+        let (sender, receiver) = mpsc::channel::<i32>();
+        sender.send(3).unwrap();
+        let value = select! {
+            recv(receiver) -> value => value.unwrap(),
+            timeout(Duration::MAX) => -1,
+        };
+        assert_eq!(value, 3);
+    }
+
+    #[test]
+    fn disconnected_receiver_fires_with_an_error() {
+        let (sender, receiver) = mpsc::channel::<i32>();
+        drop(sender);
+        let value = select! {
+            recv(receiver) -> value => value,
+            timeout(Duration::from_secs(10)) => Ok(-1),
+        };
+        assert_eq!(value, Err(RecvError));
+    }
+
+    #[test]
+    fn crate_channels_wake_a_parked_select() {
+        use crate::channel::{mpmc, spsc};
+
+        let (sender, receiver) = mpmc::unbounded();
+        let (producer, consumer) = spsc::channel::<i32>(4);
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            sender.send(5).unwrap();
+        });
+        let value = select! {
+            recv(receiver) -> value => value.unwrap(),
+            recv(consumer) -> _value => 0,
+        };
+        assert_eq!(value, 5);
+        sender.join().unwrap();
+
+        // A disconnect wakes it too
+        let dropper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            drop(producer);
+        });
+        let value = select! {
+            recv(consumer) -> value => value,
+        };
+        assert_eq!(value, Err(RecvError));
+        dropper.join().unwrap();
+    }
+
+    #[test]
+    fn wakers_forget_unregistered_and_dropped_selects() {
+        let wakers = Wakers::default();
+        let kept = Waker::new();
+        let removed = Waker::new();
+        let dropped = Waker::new();
+        for waker in [&kept, &removed, &dropped] {
+            wakers.register(waker);
+        }
+        wakers.unregister(&removed);
+        drop(dropped);
+        wakers.wake_all();
+        assert_eq!(wakers.registered.load(Ordering::Relaxed), 1);
+        assert!(*kept.signal.woken.lock().unwrap());
+        assert!(!*removed.signal.woken.lock().unwrap());
+
+        // A wakeup sent before parking is not lost
+        kept.park(None);
+        assert!(!*kept.signal.woken.lock().unwrap());
+    }
+
+    #[test]
+    fn ready_channels_are_picked_fairly() {
+        let (first, first_receiver) = mpsc::channel();
+        let (second, second_receiver) = mpsc::channel();
+        for _ in 0..1000 {
+            first.send(0).unwrap();
+            second.send(1).unwrap();
+        }
+        let mut picks = [0; 2];
+        for _ in 0..1000 {
+            let index: usize = select! {
+                recv(first_receiver) -> value => value.unwrap(),
+                recv(second_receiver) -> value => value.unwrap(),
+            };
+            picks[index] += 1;
+        }
+        // Both channels are always ready; each should win roughly half the time
+        assert!(picks.iter().all(|&count| count > 350), "{:?}", picks);
+    }
+}
@@ -0,0 +1,473 @@
+//! A bounded single-producer, single-consumer ring buffer.
+//!
+//! With exactly one thread on each side no lock and no compare-and-swap is
+//! needed: the [`Producer`] alone advances `tail`, the [`Consumer`] alone
+//! advances `head`, and each only reads the other's index to know how much
+//! room or data there is. Both `try_push` and `try_pop` finish in a bounded
+//! number of steps whatever the other thread does. The two indices live on
+//! separate cache lines, and each side keeps a private copy of the other's
+//! index so it only reads the shared one when its copy says the ring is full
+//! (or empty).
+//!
+//! The blocking [`Producer::push`] and [`Consumer::pop`] park the thread when
+//! there is no room or no data. A side about to park raises its `parked` flag
+//! and looks at the ring once more; the other side checks the flag after
+//! moving its index, with a `SeqCst` fence on both sides so at least one of
+//! them sees the other.
+
+use std::cell::{Cell, UnsafeCell};
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, fence};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use crate::cache_padded::CachePadded;
+use crate::channel::select::{TryRecv, Waker, Wakers};
+use crate::deadline;
+
+/// One side of the ring, as seen by the other
+struct Side {
+    alive: AtomicBool,
+    /// Set while this side is about to park or parked
+    parked: AtomicBool,
+    /// Thread of this side, recorded before it parks
+    thread: Mutex<Option<Thread>>,
+}
+
+impl Side {
+    fn new() -> Side {
+        Side {
+            alive: AtomicBool::new(true),
+            parked: AtomicBool::new(false),
+            thread: Mutex::new(None),
+        }
+    }
+
+    /// Unparks this side if it announced it may park.
+    ///
+    /// Clearing the flag here means a side that stays parked for a while costs
+    /// the other side one unpark, not one per value.
+    fn wake(&self) {
+        fence(Ordering::SeqCst);
+        if self.parked.load(Ordering::Relaxed)
+            && self.parked.swap(false, Ordering::Relaxed)
+            && let Some(thread) = self.thread.lock().unwrap().as_ref()
+        {
+            thread.unpark();
+        }
+    }
+
+    /// Parks the current thread unless `ready` turns true after announcing it
+    fn park_unless(&self, deadline: Option<Instant>, ready: impl Fn() -> bool) {
+        *self.thread.lock().unwrap() = Some(thread::current());
+        self.parked.store(true, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        if !ready() {
+            match deadline {
+                None => thread::park(),
+                Some(deadline) => {
+                    thread::park_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+            }
+        }
+        self.parked.store(false, Ordering::Relaxed);
+    }
+}
+
+struct Shared<T> {
+    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// Number of values popped so far; the next value to pop is at `head % capacity`
+    head: CachePadded<AtomicUsize>,
+    /// Number of values pushed so far; the next free slot is at `tail % capacity`
+    tail: CachePadded<AtomicUsize>,
+    producer: Side,
+    consumer: Side,
+    /// Selects parked on the consumer, woken along with it
+    wakers: Wakers,
+}
+
+// SAFETY: a slot is written only by the producer while it is outside
+// `head..tail` and read only by the consumer while it is inside, and the
+// Release/Acquire pairs on the indices order these accesses
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        self.buffer[index % self.capacity()].get()
+    }
+
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        self.tail.load(Ordering::Acquire).wrapping_sub(head)
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        for index in head..tail {
+            // SAFETY: slots in `head..tail` hold values nobody popped
+            unsafe { (*self.slot(index)).assume_init_drop() };
+        }
+    }
+}
+
+/// Creates a ring buffer holding at most `capacity` values
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn channel<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0, "a ring buffer needs room for one value");
+    let shared = Arc::new(Shared {
+        buffer: (0..capacity)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect(),
+        head: CachePadded::new(AtomicUsize::new(0)),
+        tail: CachePadded::new(AtomicUsize::new(0)),
+        producer: Side::new(),
+        consumer: Side::new(),
+        wakers: Wakers::default(),
+    });
+    let consumer = Consumer {
+        shared: Arc::clone(&shared),
+        head: Cell::new(0),
+        tail: Cell::new(0),
+    };
+    let producer = Producer {
+        shared,
+        head: Cell::new(0),
+        tail: Cell::new(0),
+    };
+    (producer, consumer)
+}
+
+/// The only writing end; it can move to another thread but not be shared
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+    /// Last `head` read from the consumer, never ahead of the real one
+    head: Cell<usize>,
+    /// Own copy of `tail`
+    tail: Cell<usize>,
+}
+
+impl<T> Producer<T> {
+    /// Pushes `value` if there is room, without blocking
+    pub fn try_push(&self, value: T) -> Result<(), TrySendError<T>> {
+        if !self.shared.consumer.alive.load(Ordering::Relaxed) {
+            return Err(TrySendError::Disconnected(value));
+        }
+        let tail = self.tail.get();
+        if tail.wrapping_sub(self.head.get()) == self.shared.capacity() {
+            // Looks full: find out how far the consumer really is
+            self.head.set(self.shared.head.load(Ordering::Acquire));
+            if tail.wrapping_sub(self.head.get()) == self.shared.capacity() {
+                return Err(TrySendError::Full(value));
+            }
+        }
+        // SAFETY: the slot is outside `head..tail`, so the consumer is done with it
+        unsafe { (*self.shared.slot(tail)).write(value) };
+        self.tail.set(tail.wrapping_add(1));
+        self.shared
+            .tail
+            .store(tail.wrapping_add(1), Ordering::Release);
+        self.shared.consumer.wake();
+        self.shared.wakers.wake_all();
+        Ok(())
+    }
+
+    /// Pushes `value`, parking until there is room; fails once the consumer is gone
+    pub fn push(&self, value: T) -> Result<(), SendError<T>> {
+        let mut value = value;
+        loop {
+            match self.try_push(value) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(value)) => return Err(SendError(value)),
+                Err(TrySendError::Full(rejected)) => value = rejected,
+            }
+            self.shared.producer.park_unless(None, || {
+                !self.shared.consumer.alive.load(Ordering::Relaxed)
+                    || self
+                        .tail
+                        .get()
+                        .wrapping_sub(self.shared.head.load(Ordering::Relaxed))
+                        < self.shared.capacity()
+            });
+        }
+    }
+
+    /// Maximum number of queued values
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    /// Number of queued values; the consumer may be taking some right now
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    /// Whether no value is queued
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the consumer was dropped
+    pub fn is_disconnected(&self) -> bool {
+        !self.shared.consumer.alive.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.shared.producer.alive.store(false, Ordering::Release);
+        self.shared.consumer.wake();
+        self.shared.wakers.wake_all();
+    }
+}
+
+impl<T> fmt::Debug for Producer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Producer")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+/// The only reading end; it can move to another thread but not be shared
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+    /// Own copy of `head`
+    head: Cell<usize>,
+    /// Last `tail` read from the producer, never ahead of the real one
+    tail: Cell<usize>,
+}
+
+impl<T> Consumer<T> {
+    /// Pops the oldest value without blocking
+    pub fn try_pop(&self) -> Result<T, TryRecvError> {
+        let head = self.head.get();
+        if head == self.tail.get() {
+            // Looks empty: find out how far the producer really is
+            self.tail.set(self.shared.tail.load(Ordering::Acquire));
+            if head == self.tail.get() {
+                // The producer pushes before it leaves, so look once more after seeing it gone
+                if self.shared.producer.alive.load(Ordering::Acquire) {
+                    return Err(TryRecvError::Empty);
+                }
+                self.tail.set(self.shared.tail.load(Ordering::Acquire));
+                if head == self.tail.get() {
+                    return Err(TryRecvError::Disconnected);
+                }
+            }
+        }
+        // SAFETY: the slot is inside `head..tail`, so the producer has written it
+        let value = unsafe { (*self.shared.slot(head)).assume_init_read() };
+        self.head.set(head.wrapping_add(1));
+        self.shared
+            .head
+            .store(head.wrapping_add(1), Ordering::Release);
+        self.shared.producer.wake();
+        Ok(value)
+    }
+
+    /// Pops the oldest value, parking until one arrives or the producer is gone
+    pub fn pop(&self) -> Result<T, RecvError> {
+        self.pop_until(None).map_err(|_| RecvError)
+    }
+
+    /// Like [`pop`](Consumer::pop), waiting at most `timeout`
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.pop_until(deadline::after(timeout))
+    }
+
+    fn pop_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        loop {
+            match self.try_pop() {
+                Ok(value) => return Ok(value),
+                Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+                Err(TryRecvError::Empty) => {}
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            self.shared.consumer.park_unless(deadline, || {
+                !self.shared.producer.alive.load(Ordering::Relaxed)
+                    || self.shared.tail.load(Ordering::Relaxed) != self.head.get()
+            });
+        }
+    }
+
+    /// Iterates over values until the producer is gone and the ring is drained
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.pop().ok())
+    }
+
+    /// Iterates over the values that are queued right now
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_pop().ok())
+    }
+
+    /// Maximum number of queued values
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    /// Number of queued values; the producer may be adding some right now
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    /// Whether no value is queued
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.shared.consumer.alive.store(false, Ordering::Release);
+        self.shared.producer.wake();
+    }
+}
+
+impl<T> fmt::Debug for Consumer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Consumer")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+impl<T> TryRecv for Consumer<T> {
+    type Item = T;
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.try_pop()
+    }
+
+    fn register(&self, waker: &Waker) -> bool {
+        self.shared.wakers.register(waker);
+        true
+    }
+
+    fn unregister(&self, waker: &Waker) {
+        self.shared.wakers.unregister(waker);
+    }
+}
+
+/// Owning iterator over the values of a consumer
+pub struct IntoIter<T> {
+    consumer: Consumer<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.consumer.pop().ok()
+    }
+}
+
+impl<T> IntoIterator for Consumer<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { consumer: self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_up_and_wraps_around() {
+        let (producer, consumer) = channel(3);
+        for round in 0..4 {
+            for value in 0..3 {
+                producer.try_push(round * 10 + value).unwrap();
+            }
+            assert_eq!(producer.try_push(99), Err(TrySendError::Full(99)));
+            assert_eq!(consumer.len(), 3);
+            let drained: Vec<_> = consumer.try_iter().collect();
+            assert_eq!(drained, vec![round * 10, round * 10 + 1, round * 10 + 2]);
+            assert_eq!(consumer.try_pop(), Err(TryRecvError::Empty));
+        }
+    }
+
+    #[test]
+    fn blocking_ends_keep_order_across_threads() {
+        let (producer, consumer) = channel(4);
+        let pushing = thread::spawn(move || {
+            for value in 0..20_000 {
+                producer.push(value).unwrap();
+            }
+        });
+        let mut expected = 0;
+        for value in consumer {
+            assert_eq!(value, expected);
+            expected += 1;
+        }
+        pushing.join().unwrap();
+        assert_eq!(expected, 20_000);
+    }
+
+    #[test]
+    fn disconnects_and_timeouts() {
+        let (producer, consumer) = channel::<i32>(1);
+        assert_eq!(
+            consumer.pop_timeout(Duration::from_millis(5)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        producer.push(1).unwrap();
+        let blocked = thread::spawn(move || producer.push(2));
+        thread::sleep(Duration::from_millis(5));
+        drop(consumer);
+        // The parked producer wakes up and gets its value back
+        assert_eq!(blocked.join().unwrap(), Err(SendError(2)));
+
+        let (producer, consumer) = channel(2);
+        producer.push("last").unwrap();
+        drop(producer);
+        assert_eq!(consumer.pop(), Ok("last"));
+        assert_eq!(consumer.pop(), Err(RecvError));
+
+        // A timeout too long for an `Instant` waits like `pop`
+        let (producer, consumer) = channel(1);
+        let pushing = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(5));
+            producer.push(3).unwrap();
+        });
+        assert_eq!(consumer.pop_timeout(Duration::MAX), Ok(3));
+        pushing.join().unwrap();
+        assert_eq!(
+            consumer.pop_timeout(Duration::MAX),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn unpopped_values_are_dropped() {
+        let value = Arc::new(());
+        let (producer, consumer) = channel(4);
+        for _ in 0..3 {
+            producer.push(Arc::clone(&value)).unwrap();
+        }
+        drop(consumer.pop());
+        drop(producer);
+        drop(consumer);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
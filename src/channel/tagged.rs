@@ -0,0 +1,440 @@
+//! An `mpsc` channel whose messages say who sent them and in which order.
+//!
+//! Every [`Sender`] has a producer id; the one returned by [`channel`] is 0
+//! and each clone takes the next free id. A sender numbers its messages from
+//! 0, so the receiver gets [`Tagged`] values it can feed to an
+//! [`OrderChecker`]. The checker verifies the FIFO order of each producer,
+//! reports gaps, duplicates and late arrivals, and measures how the producers
+//! were interleaved.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvError, RecvTimeoutError, SendError, TryRecvError};
+use std::time::Duration;
+
+use crate::channel::select::{SenderWakers, TryRecv, Waker, Wakers};
+
+/// A message with the identity of its sender
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tagged<T> {
+    /// Id of the sender
+    pub producer: usize,
+    /// Position of the message among those of its sender, from 0
+    pub sequence: u64,
+    pub value: T,
+}
+
+/// Creates a channel whose first sender is producer 0
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = mpsc::channel();
+    let wakers = Arc::new(Wakers::default());
+    let sender = Sender {
+        inner: sender,
+        producer: 0,
+        next_sequence: 0,
+        next_producer: Arc::new(AtomicUsize::new(1)),
+        wakers: SenderWakers(Arc::clone(&wakers)),
+    };
+    let receiver = Receiver {
+        inner: receiver,
+        wakers,
+    };
+    (sender, receiver)
+}
+
+/// The sending half; every clone is a new producer with its own sequence
+pub struct Sender<T> {
+    inner: mpsc::Sender<Tagged<T>>,
+    producer: usize,
+    next_sequence: u64,
+    /// Id handed to the next clone
+    next_producer: Arc<AtomicUsize>,
+    /// Selects parked on the receiver; declared after `inner` to be dropped after it
+    wakers: SenderWakers,
+}
+
+impl<T> Sender<T> {
+    /// Tags `value` with this producer's id and next sequence number and sends it
+    pub fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        let tagged = Tagged {
+            producer: self.producer,
+            sequence: self.next_sequence,
+            value,
+        };
+        self.inner
+            .send(tagged)
+            .map_err(|SendError(tagged)| SendError(tagged.value))?;
+        self.next_sequence += 1;
+        self.wakers.0.wake_all();
+        Ok(())
+    }
+
+    /// Id of this producer
+    pub fn producer(&self) -> usize {
+        self.producer
+    }
+
+    /// Number of messages this producer has sent
+    pub fn sent(&self) -> u64 {
+        self.next_sequence
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender {
+            inner: self.inner.clone(),
+            producer: self.next_producer.fetch_add(1, Ordering::Relaxed),
+            next_sequence: 0,
+            next_producer: Arc::clone(&self.next_producer),
+            wakers: self.wakers.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("producer", &self.producer)
+            .field("sent", &self.next_sequence)
+            .finish()
+    }
+}
+
+/// The receiving half
+pub struct Receiver<T> {
+    inner: mpsc::Receiver<Tagged<T>>,
+    wakers: Arc<Wakers>,
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until a message arrives or every sender is gone
+    pub fn recv(&self) -> Result<Tagged<T>, RecvError> {
+        self.inner.recv()
+    }
+
+    /// Returns a queued message without blocking
+    pub fn try_recv(&self) -> Result<Tagged<T>, TryRecvError> {
+        self.inner.try_recv()
+    }
+
+    /// Waits at most `timeout` for a message
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Tagged<T>, RecvTimeoutError> {
+        self.inner.recv_timeout(timeout)
+    }
+
+    /// Iterates over messages until every sender is gone
+    pub fn iter(&self) -> mpsc::Iter<'_, Tagged<T>> {
+        self.inner.iter()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<T> TryRecv for Receiver<T> {
+    type Item = Tagged<T>;
+
+    fn try_recv(&self) -> Result<Tagged<T>, TryRecvError> {
+        self.inner.try_recv()
+    }
+
+    fn register(&self, waker: &Waker) -> bool {
+        self.wakers.register(waker);
+        true
+    }
+
+    fn unregister(&self, waker: &Waker) {
+        self.wakers.unregister(waker);
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = Tagged<T>;
+    type IntoIter = mpsc::IntoIter<Tagged<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.into_iter()
+    }
+}
+
+/// How one message fits the order of its producer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Observation {
+    /// The next expected sequence number
+    InOrder,
+    /// Sequence numbers from `expected` up to the received one were skipped
+    Gap { expected: u64 },
+    /// A skipped sequence number arrived late
+    Reordered,
+    /// This sequence number was already received
+    Duplicate,
+}
+
+/// What the checker saw from one producer
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProducerStats {
+    pub received: u64,
+    /// Sequence numbers skipped and never received so far
+    pub missing: u64,
+    pub duplicates: u64,
+    pub reordered: u64,
+}
+
+impl ProducerStats {
+    /// Whether every message arrived exactly once and in order
+    pub fn is_ordered(&self) -> bool {
+        self.missing == 0 && self.duplicates == 0 && self.reordered == 0
+    }
+}
+
+#[derive(Debug, Default)]
+struct ProducerState {
+    stats: ProducerStats,
+    expected: u64,
+    /// Skipped sequence numbers that may still arrive, as `start..end` ranges
+    /// keyed by their start
+    missing: BTreeMap<u64, u64>,
+}
+
+impl ProducerState {
+    /// Takes `sequence` out of the gaps, `false` if it was not missing
+    fn fill_gap(&mut self, sequence: u64) -> bool {
+        let Some((&start, &end)) = self.missing.range(..=sequence).next_back() else {
+            return false;
+        };
+        if sequence >= end {
+            return false;
+        }
+        self.missing.remove(&start);
+        if start < sequence {
+            self.missing.insert(start, sequence);
+        }
+        if sequence + 1 < end {
+            self.missing.insert(sequence + 1, end);
+        }
+        true
+    }
+}
+
+/// Verifies per-producer FIFO order on the receiving side
+#[derive(Debug, Default)]
+pub struct OrderChecker {
+    producers: BTreeMap<usize, ProducerState>,
+    total: u64,
+    /// Producer of the previous message and how many came from it in a row
+    run: Option<(usize, u64)>,
+    switches: u64,
+    longest_run: u64,
+}
+
+impl OrderChecker {
+    /// Creates a checker that has seen nothing
+    pub fn new() -> Self {
+        OrderChecker::default()
+    }
+
+    /// Records a received message
+    pub fn check<T>(&mut self, message: &Tagged<T>) -> Observation {
+        self.observe(message.producer, message.sequence)
+    }
+
+    /// Records that `producer` delivered its message number `sequence`
+    pub fn observe(&mut self, producer: usize, sequence: u64) -> Observation {
+        self.total += 1;
+        let run = match self.run {
+            Some((previous, length)) if previous == producer => length + 1,
+            Some(_) => {
+                self.switches += 1;
+                1
+            }
+            None => 1,
+        };
+        self.run = Some((producer, run));
+        self.longest_run = self.longest_run.max(run);
+
+        let state = self.producers.entry(producer).or_default();
+        state.stats.received += 1;
+        if sequence == state.expected {
+            state.expected += 1;
+            Observation::InOrder
+        } else if sequence > state.expected {
+            let expected = state.expected;
+            state.missing.insert(expected, sequence);
+            state.expected = sequence.saturating_add(1);
+            state.stats.missing += sequence - expected;
+            Observation::Gap { expected }
+        } else if state.fill_gap(sequence) {
+            state.stats.missing -= 1;
+            state.stats.reordered += 1;
+            Observation::Reordered
+        } else {
+            state.stats.duplicates += 1;
+            Observation::Duplicate
+        }
+    }
+
+    /// Summarizes everything observed so far
+    pub fn report(&self) -> OrderReport {
+        OrderReport {
+            producers: self
+                .producers
+                .iter()
+                .map(|(&producer, state)| (producer, state.stats.clone()))
+                .collect(),
+            total: self.total,
+            switches: self.switches,
+            longest_run: self.longest_run,
+        }
+    }
+}
+
+/// Ordering and interleaving of a run, per producer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderReport {
+    pub producers: BTreeMap<usize, ProducerStats>,
+    /// Messages observed
+    pub total: u64,
+    /// Times two consecutive messages came from different producers
+    pub switches: u64,
+    /// Most consecutive messages from the same producer
+    pub longest_run: u64,
+}
+
+impl OrderReport {
+    /// Whether every producer's messages arrived exactly once and in order
+    pub fn is_ordered(&self) -> bool {
+        self.producers.values().all(ProducerStats::is_ordered)
+    }
+
+    /// Share of consecutive pairs that switched producer, from 0 (one producer
+    /// after the other) to 1 (strict alternation)
+    pub fn interleaving(&self) -> f64 {
+        if self.total < 2 {
+            0.0
+        } else {
+            self.switches as f64 / (self.total - 1) as f64
+        }
+    }
+}
+
+impl fmt::Display for OrderReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} messages from {} producers, {} ({:.0}% interleaving, longest run {})",
+            self.total,
+            self.producers.len(),
+            if self.is_ordered() {
+                "in order"
+            } else {
+                "OUT OF ORDER"
+            },
+            self.interleaving() * 100.0,
+            self.longest_run
+        )?;
+        for (producer, stats) in &self.producers {
+            write!(
+                f,
+                "\n  producer {}: {} received, {} missing, {} duplicates, {} reordered",
+                producer, stats.received, stats.missing, stats.duplicates, stats.reordered
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn clones_get_their_own_id_and_sequence() {
+        let (mut sender, receiver) = channel();
+        let mut other = sender.clone();
+        sender.send("a").unwrap();
+        other.send("b").unwrap();
+        sender.send("c").unwrap();
+        assert_eq!(other.producer(), 1);
+        drop(sender);
+        drop(other);
+        let received: Vec<_> = receiver
+            .into_iter()
+            .map(|message| (message.producer, message.sequence, message.value))
+            .collect();
+        assert_eq!(received, vec![(0, 0, "a"), (1, 0, "b"), (0, 1, "c")]);
+    }
+
+    #[test]
+    fn threads_keep_per_producer_order() {
+        let (sender, receiver) = channel();
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let mut sender = sender.clone();
+                thread::spawn(move || {
+                    for value in 0..100 {
+                        sender.send(value).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+        let mut checker = OrderChecker::new();
+        for message in receiver {
+            assert_eq!(checker.check(&message), Observation::InOrder);
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let report = checker.report();
+        assert!(report.is_ordered());
+        assert_eq!(report.total, 300);
+        assert_eq!(
+            report.producers.keys().copied().collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn gaps_duplicates_and_late_arrivals() {
+        let mut checker = OrderChecker::new();
+        assert_eq!(checker.observe(0, 0), Observation::InOrder);
+        assert_eq!(checker.observe(0, 3), Observation::Gap { expected: 1 });
+        assert_eq!(checker.observe(0, 1), Observation::Reordered);
+        assert_eq!(checker.observe(0, 1), Observation::Duplicate);
+        assert_eq!(checker.observe(1, 0), Observation::InOrder);
+        let report = checker.report();
+        assert!(!report.is_ordered());
+        assert_eq!(
+            report.producers[&0],
+            ProducerStats {
+                received: 4,
+                missing: 1,
+                duplicates: 1,
+                reordered: 1,
+            }
+        );
+        assert_eq!(report.switches, 1);
+        assert_eq!(report.longest_run, 4);
+        assert!((report.interleaving() - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn huge_gaps_are_kept_as_ranges() {
+        let mut checker = OrderChecker::new();
+        let far = u64::MAX / 2;
+        assert_eq!(checker.observe(0, far), Observation::Gap { expected: 0 });
+        assert_eq!(checker.observe(0, 5), Observation::Reordered);
+        assert_eq!(checker.observe(0, 5), Observation::Duplicate);
+        assert_eq!(checker.observe(0, 0), Observation::Reordered);
+        assert_eq!(checker.observe(0, far - 1), Observation::Reordered);
+        assert_eq!(checker.observe(0, far), Observation::Duplicate);
+        assert_eq!(checker.report().producers[&0].missing, far - 3);
+    }
+}
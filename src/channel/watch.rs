@@ -0,0 +1,298 @@
+//! A channel that only keeps the latest value.
+//!
+//! Instead of a queue there is one value and a version number bumped by every
+//! send. Receivers [`borrow`](Receiver::borrow) the current value whenever
+//! they like and [`changed`](Receiver::changed) blocks until a newer version
+//! than the last one they saw exists. Intermediate versions are not kept, but
+//! the returned [`Change`] tells how many of them a receiver skipped.
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard};
+use std::time::{Duration, Instant};
+
+use crate::deadline;
+
+struct Shared<T> {
+    value: RwLock<T>,
+    /// Updated after the value, so a new version always has its value in place
+    version: Mutex<Version>,
+    /// Signalled on every new version and when the sender is dropped
+    changed: Condvar,
+    receivers: AtomicUsize,
+}
+
+struct Version {
+    number: u64,
+    closed: bool,
+}
+
+impl<T> Shared<T> {
+    fn version(&self) -> MutexGuard<'_, Version> {
+        self.version.lock().unwrap()
+    }
+}
+
+/// A newer version seen by [`Receiver::changed`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    /// The version now current
+    pub version: u64,
+    /// Versions that came and went since the receiver last looked
+    pub missed: u64,
+}
+
+/// Creates a watch channel holding `initial` as version 0
+pub fn channel<T>(initial: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        value: RwLock::new(initial),
+        version: Mutex::new(Version {
+            number: 0,
+            closed: false,
+        }),
+        changed: Condvar::new(),
+        receivers: AtomicUsize::new(1),
+    });
+    let receiver = Receiver {
+        shared: Arc::clone(&shared),
+        seen: 0,
+    };
+    (Sender { shared }, receiver)
+}
+
+/// The single publishing half
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Publishes `value` unless every receiver is gone, in which case it is given back
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        if self.receiver_count() == 0 {
+            return Err(SendError(value));
+        }
+        self.send_replace(value);
+        Ok(())
+    }
+
+    /// Publishes `value` even without receivers and returns the previous value
+    pub fn send_replace(&self, value: T) -> T {
+        let previous = std::mem::replace(&mut *self.shared.value.write().unwrap(), value);
+        self.shared.version().number += 1;
+        self.shared.changed.notify_all();
+        previous
+    }
+
+    /// The current value
+    pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+        self.shared.value.read().unwrap()
+    }
+
+    /// The current version, bumped by every send
+    pub fn version(&self) -> u64 {
+        self.shared.version().number
+    }
+
+    /// Creates a receiver that has seen the current version
+    pub fn subscribe(&self) -> Receiver<T> {
+        self.shared.receivers.fetch_add(1, Ordering::Relaxed);
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            seen: self.version(),
+        }
+    }
+
+    /// Number of live receivers
+    pub fn receiver_count(&self) -> usize {
+        self.shared.receivers.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.version().closed = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("value", &*self.borrow())
+            .field("version", &self.version())
+            .finish()
+    }
+}
+
+/// A receiving half; clone it to get another receiver that has seen the same version
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    /// Latest version this receiver has looked at
+    seen: u64,
+}
+
+impl<T> Receiver<T> {
+    /// The current value, without marking it as seen
+    pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+        self.shared.value.read().unwrap()
+    }
+
+    /// The current value, marking its version as seen
+    pub fn borrow_and_update(&mut self) -> RwLockReadGuard<'_, T> {
+        // Read the version first: the value can only be as new or newer
+        self.seen = self.shared.version().number;
+        self.shared.value.read().unwrap()
+    }
+
+    /// Latest version this receiver has seen
+    pub fn seen_version(&self) -> u64 {
+        self.seen
+    }
+
+    /// Whether a version newer than the seen one exists; fails once the sender is gone
+    pub fn has_changed(&self) -> Result<bool, RecvError> {
+        let version = self.shared.version();
+        if version.number > self.seen {
+            Ok(true)
+        } else if version.closed {
+            Err(RecvError)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Blocks until a newer version than the seen one exists and marks it as seen.
+    ///
+    /// Fails once the sender is gone and the latest version has been seen.
+    pub fn changed(&mut self) -> Result<Change, RecvError> {
+        self.changed_until(None).map_err(|_| RecvError)
+    }
+
+    /// Like [`changed`](Receiver::changed), waiting at most `timeout`
+    pub fn changed_timeout(&mut self, timeout: Duration) -> Result<Change, RecvTimeoutError> {
+        self.changed_until(deadline::after(timeout))
+    }
+
+    fn changed_until(&mut self, deadline: Option<Instant>) -> Result<Change, RecvTimeoutError> {
+        let seen = &mut self.seen;
+        deadline::recv_until(
+            &self.shared.changed,
+            self.shared.version(),
+            deadline,
+            |version| {
+                if version.number <= *seen {
+                    return None;
+                }
+                let change = Change {
+                    version: version.number,
+                    missed: version.number - *seen - 1,
+                };
+                *seen = version.number;
+                Some(change)
+            },
+            |version| version.closed,
+        )
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.receivers.fetch_add(1, Ordering::Relaxed);
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            seen: self.seen,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receivers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("value", &*self.borrow())
+            .field("seen", &self.seen)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn only_the_latest_value_is_kept() {
+        let (sender, mut receiver) = channel("initial");
+        assert_eq!(*receiver.borrow(), "initial");
+        assert_eq!(receiver.has_changed(), Ok(false));
+        assert_eq!(sender.send_replace("first"), "initial");
+        sender.send("second").unwrap();
+        assert_eq!(receiver.has_changed(), Ok(true));
+        assert_eq!(
+            receiver.changed(),
+            Ok(Change {
+                version: 2,
+                missed: 1
+            })
+        );
+        assert_eq!(*receiver.borrow(), "second");
+        assert_eq!(receiver.has_changed(), Ok(false));
+    }
+
+    #[test]
+    fn changed_blocks_until_a_new_version() {
+        let (sender, mut receiver) = channel(0);
+        let watcher = thread::spawn(move || {
+            let mut seen = Vec::new();
+            while receiver.changed().is_ok() {
+                seen.push(*receiver.borrow());
+            }
+            seen
+        });
+        for value in 1..=3 {
+            thread::sleep(Duration::from_millis(5));
+            sender.send(value).unwrap();
+        }
+        drop(sender);
+        let seen = watcher.join().unwrap();
+        // Updates may be coalesced, but the last one is never lost
+        assert_eq!(seen.last(), Some(&3));
+        assert!(seen.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn timeouts_subscribers_and_closing() {
+        let (sender, receiver) = channel(1);
+        let mut late = sender.subscribe();
+        sender.send(2).unwrap();
+        let mut copy = late.clone();
+        assert_eq!(late.changed().map(|change| change.version), Ok(1));
+        assert_eq!(
+            late.changed_timeout(Duration::from_millis(5)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        assert_eq!(*copy.borrow_and_update(), 2);
+        assert_eq!(copy.seen_version(), 1);
+        sender.send(4).unwrap();
+        assert_eq!(
+            late.changed_timeout(Duration::MAX)
+                .map(|change| change.version),
+            Ok(2)
+        );
+        drop(receiver);
+        drop(late);
+        drop(copy);
+        assert_eq!(sender.send(3), Err(SendError(3)));
+
+        let (sender, mut receiver) = channel(1);
+        drop(sender);
+        assert_eq!(receiver.changed(), Err(RecvError));
+        assert_eq!(*receiver.borrow(), 1);
+    }
+}
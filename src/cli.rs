@@ -0,0 +1,788 @@
+//! Command line front end: `rust-concurrency list`,
+//! `rust-concurrency run <demo> [--threads N] [--iterations M] [--delay-ms D]`,
+//! `rust-concurrency bench <workload> [options]` and
+//! `rust-concurrency litmus [patterns] [options]`.
+
+use std::fmt;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::bench::{self, BenchConfig, Report, Workload};
+use crate::clock::RealClock;
+use crate::counter;
+use crate::demos::{self, Demo, DemoConfig};
+use crate::litmus::{self, Pattern};
+
+/// Usage text printed by `help` and after a parse error
+pub const USAGE: &str = "\
+usage: rust-concurrency <command>
+
+commands:
+  list                          list the available demos and their defaults
+  run <demo> [options]          run a demo
+  bench <sleep|spin> [options]  compare a workload run sequentially and in parallel
+  bench channel [options]       compare ring buffer and batched channel throughput with std mpsc
+  bench counter [options]       run the increment workload against every counter strategy
+  bench false-sharing [options] time per-thread counters packed together and cache-padded
+  litmus [mp|sb|lb|iriw...]     count the outcomes of memory ordering litmus tests (default all)
+  help                          print this message
+
+options for run:
+  --threads N                   number of threads the demo spawns
+  --iterations M                number of steps per thread
+  --delay-ms D                  pause between two steps, in milliseconds
+
+options for bench:
+  --threads N[,N...]            thread counts to compare against one thread (default 2,4),
+                                or to run the counter workload with (default 10)
+  --units U                     units of work per run of the sleep and spin workloads (default 16)
+  --warmup W                    untimed runs before measuring (default 1)
+  --repetitions R               timed runs (default 5)
+  --delay-ms D                  sleep per unit of the sleep workload (default 10)
+  --rounds N                    spin rounds per unit of the spin workload (default 1000000)
+  --batch B[,B...]              batch sizes of the channel workload (default 1,16,256)
+  --messages M                  messages per run of the channel workload (default 100000)
+  --capacity C                  slots of the ring buffer in the channel workload (default 1024)
+  --increments M                increments per thread of the counter and false-sharing
+                                workloads (default 1000000)
+  --format table|json|csv       output format (default table)
+
+options for litmus:
+  --ordering O[,O...]           relaxed, acquire, release, acqrel and/or seqcst (default all)
+  --iterations N                runs of every pattern and ordering (default 1000000)
+  --batch B                     instances the threads race over between two barriers (default 10000)";
+
+/// A parsed command line
+#[derive(Debug)]
+pub enum Command {
+    /// Print the demo registry
+    List,
+    /// Run one demo with the given parameters
+    Run {
+        demo: &'static Demo,
+        config: DemoConfig,
+    },
+    /// Benchmark a workload for every thread count
+    Bench {
+        workload: Workload,
+        threads: Vec<usize>,
+        config: BenchConfig,
+        format: Format,
+    },
+    /// Measure channel throughput for every batch size
+    ChannelBench {
+        batches: Vec<usize>,
+        capacity: usize,
+        config: BenchConfig,
+        format: Format,
+    },
+    /// Run the increment workload for every counter strategy and thread count
+    CounterBench {
+        threads: Vec<usize>,
+        config: BenchConfig,
+        format: Format,
+    },
+    /// Time adjacent and padded per-thread counters for every thread count
+    FalseSharingBench {
+        threads: Vec<usize>,
+        config: BenchConfig,
+        format: Format,
+    },
+    /// Run litmus tests for every pattern and ordering
+    Litmus {
+        patterns: Vec<Pattern>,
+        orderings: Vec<Ordering>,
+        iterations: usize,
+        batch: usize,
+    },
+    /// Print the usage text
+    Help,
+}
+
+/// Output formats of the `bench` command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+/// Reasons a command line cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliError {
+    /// No command was given
+    MissingCommand,
+    /// The first argument is not a known command
+    UnknownCommand(String),
+    /// `run` was given without a demo name
+    MissingDemo,
+    /// The demo name is not in the registry
+    UnknownDemo(String),
+    /// `bench` was given without a workload name
+    MissingWorkload,
+    /// The workload name is not a built-in workload
+    UnknownWorkload(String),
+    /// An option that the command does not accept
+    UnknownOption(String),
+    /// An option was given without its value
+    MissingValue(&'static str),
+    /// An option value could not be parsed
+    InvalidValue { option: &'static str, value: String },
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::MissingCommand => write!(f, "no command given"),
+            CliError::UnknownCommand(command) => write!(f, "unknown command `{}`", command),
+            CliError::MissingDemo => write!(f, "`run` needs a demo name, see `list`"),
+            CliError::UnknownDemo(name) => write!(f, "unknown demo `{}`, see `list`", name),
+            CliError::MissingWorkload => {
+                write!(f, "`bench` needs a workload, see `help`")
+            }
+            CliError::UnknownWorkload(name) => {
+                write!(
+                    f,
+                    "unknown workload `{}`, use `sleep`, `spin`, `channel`, `counter` or \
+                     `false-sharing`",
+                    name
+                )
+            }
+            CliError::UnknownOption(option) => write!(f, "unknown option `{}`", option),
+            CliError::MissingValue(option) => write!(f, "`{}` needs a value", option),
+            CliError::InvalidValue { option, value } => {
+                write!(f, "invalid value `{}` for `{}`", value, option)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// Parses the arguments that follow the program name
+pub fn parse<I>(args: I) -> Result<Command, CliError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let command = args.next().ok_or(CliError::MissingCommand)?;
+    match command.as_str() {
+        "list" => match args.next() {
+            Some(extra) => Err(CliError::UnknownOption(extra)),
+            None => Ok(Command::List),
+        },
+        "help" | "--help" | "-h" => Ok(Command::Help),
+        "run" => parse_run(args),
+        "bench" => parse_bench(args),
+        "litmus" => parse_litmus(args),
+        _ => Err(CliError::UnknownCommand(command)),
+    }
+}
+
+/// Parses the arguments of `run`
+fn parse_run(mut args: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let name = args.next().ok_or(CliError::MissingDemo)?;
+    let demo = demos::find(&name).ok_or(CliError::UnknownDemo(name))?;
+    let mut config = demo.defaults.clone();
+    while let Some(option) = args.next() {
+        match option.as_str() {
+            "--threads" => config.threads = value(&mut args, "--threads")?,
+            "--iterations" => config.iterations = value(&mut args, "--iterations")?,
+            "--delay-ms" => config.delay = Duration::from_millis(value(&mut args, "--delay-ms")?),
+            _ => return Err(CliError::UnknownOption(option)),
+        }
+    }
+    if config.threads == 0 {
+        return Err(CliError::InvalidValue {
+            option: "--threads",
+            value: "0".to_string(),
+        });
+    }
+    // The slowest sender of the channel demos pauses `threads * delay` per step,
+    // and the counting demos total `threads * iterations` in an `i32`
+    let steps = config.iterations.saturating_mul(config.threads);
+    if steps > i32::MAX as usize || config.delay_times(steps).is_none() {
+        return Err(CliError::InvalidValue {
+            option: "--iterations",
+            value: config.iterations.to_string(),
+        });
+    }
+    Ok(Command::Run { demo, config })
+}
+
+/// Parses the arguments of `bench`
+fn parse_bench(mut args: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let name = args.next().ok_or(CliError::MissingWorkload)?;
+    if !["sleep", "spin", "channel", "counter", "false-sharing"].contains(&name.as_str()) {
+        return Err(CliError::UnknownWorkload(name));
+    }
+    let mut threads: Option<Vec<usize>> = None;
+    let mut config = BenchConfig::default();
+    let mut delay = Duration::from_millis(10);
+    let mut rounds = 1_000_000;
+    let mut batches = vec![1, 16, 256];
+    let mut messages = 100_000;
+    let mut capacity = 1024;
+    let mut increments: usize = 1_000_000;
+    let mut format = Format::Table;
+    while let Some(option) = args.next() {
+        if !bench_option_applies(&name, &option) {
+            return Err(CliError::UnknownOption(option));
+        }
+        match option.as_str() {
+            "--threads" => threads = Some(list(&mut args, "--threads")?),
+            "--units" => config.units = value(&mut args, "--units")?,
+            "--warmup" => config.warmup = value(&mut args, "--warmup")?,
+            "--repetitions" => config.repetitions = value(&mut args, "--repetitions")?,
+            "--delay-ms" => delay = Duration::from_millis(value(&mut args, "--delay-ms")?),
+            "--rounds" => rounds = value(&mut args, "--rounds")?,
+            "--batch" => batches = list(&mut args, "--batch")?,
+            "--messages" => messages = value(&mut args, "--messages")?,
+            "--capacity" => capacity = value(&mut args, "--capacity")?,
+            "--increments" => increments = value(&mut args, "--increments")?,
+            "--format" => {
+                let value: String = value(&mut args, "--format")?;
+                format = match value.as_str() {
+                    "table" => Format::Table,
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option: "--format",
+                            value,
+                        });
+                    }
+                };
+            }
+            _ => return Err(CliError::UnknownOption(option)),
+        }
+    }
+    let threads = threads.unwrap_or_else(|| {
+        if name == "counter" {
+            vec![10]
+        } else {
+            vec![2, 4]
+        }
+    });
+    let zero = if config.units == 0 {
+        Some("--units")
+    } else if config.repetitions == 0 {
+        Some("--repetitions")
+    } else if threads.contains(&0) {
+        Some("--threads")
+    } else if batches.contains(&0) {
+        Some("--batch")
+    } else if capacity == 0 {
+        Some("--capacity")
+    } else if messages == 0 {
+        Some("--messages")
+    } else if increments == 0 {
+        Some("--increments")
+    } else {
+        None
+    };
+    if let Some(option) = zero {
+        return Err(CliError::InvalidValue {
+            option,
+            value: "0".to_string(),
+        });
+    }
+    if name == "channel" {
+        config.units = messages;
+        return Ok(Command::ChannelBench {
+            batches,
+            capacity,
+            config,
+            format,
+        });
+    }
+    if name == "counter" {
+        // The built-in counters are `i32`s, like in the demos
+        if threads
+            .iter()
+            .any(|&threads| threads.saturating_mul(increments) > i32::MAX as usize)
+        {
+            return Err(CliError::InvalidValue {
+                option: "--increments",
+                value: increments.to_string(),
+            });
+        }
+        config.units = increments;
+        return Ok(Command::CounterBench {
+            threads,
+            config,
+            format,
+        });
+    }
+    if name == "false-sharing" {
+        config.units = increments;
+        return Ok(Command::FalseSharingBench {
+            threads,
+            config,
+            format,
+        });
+    }
+    let workload = match name.as_str() {
+        "sleep" => Workload::Sleep(delay),
+        _ => Workload::Spin(rounds),
+    };
+    Ok(Command::Bench {
+        workload,
+        threads,
+        config,
+        format,
+    })
+}
+
+/// Whether `option` means anything to the bench `workload`; options no
+/// workload knows are left for the parser to reject
+fn bench_option_applies(workload: &str, option: &str) -> bool {
+    let specific: &[&str] = match workload {
+        "sleep" => &["--threads", "--units", "--delay-ms"],
+        "spin" => &["--threads", "--units", "--rounds"],
+        "channel" => &["--batch", "--messages", "--capacity"],
+        _ => &["--threads", "--increments"],
+    };
+    const WORKLOAD_OPTIONS: [&str; 8] = [
+        "--threads",
+        "--units",
+        "--delay-ms",
+        "--rounds",
+        "--batch",
+        "--messages",
+        "--capacity",
+        "--increments",
+    ];
+    specific.contains(&option) || !WORKLOAD_OPTIONS.contains(&option)
+}
+
+/// Parses the arguments of `litmus`
+fn parse_litmus(mut args: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let mut patterns = Vec::new();
+    let mut orderings = litmus::ORDERINGS.to_vec();
+    let mut iterations = 1_000_000;
+    let mut batch = 10_000;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ordering" => {
+                let names: Vec<String> = list(&mut args, "--ordering")?;
+                orderings = names
+                    .iter()
+                    .map(|name| litmus::parse_ordering(name))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| CliError::InvalidValue {
+                        option: "--ordering",
+                        value: names.join(","),
+                    })?;
+            }
+            "--iterations" => iterations = value(&mut args, "--iterations")?,
+            "--batch" => batch = value(&mut args, "--batch")?,
+            _ if arg.starts_with("--") => return Err(CliError::UnknownOption(arg)),
+            _ => match Pattern::from_name(&arg) {
+                Some(pattern) => patterns.push(pattern),
+                None => {
+                    return Err(CliError::InvalidValue {
+                        option: "litmus",
+                        value: arg,
+                    });
+                }
+            },
+        }
+    }
+    if batch == 0 {
+        return Err(CliError::InvalidValue {
+            option: "--batch",
+            value: "0".to_string(),
+        });
+    }
+    if patterns.is_empty() {
+        patterns = litmus::PATTERNS.to_vec();
+    }
+    Ok(Command::Litmus {
+        patterns,
+        orderings,
+        iterations,
+        batch,
+    })
+}
+
+/// Takes the value following `option` and parses it
+fn value<T, I>(args: &mut I, option: &'static str) -> Result<T, CliError>
+where
+    T: std::str::FromStr,
+    I: Iterator<Item = String>,
+{
+    let value = args.next().ok_or(CliError::MissingValue(option))?;
+    value
+        .parse()
+        .map_err(|_| CliError::InvalidValue { option, value })
+}
+
+/// Takes the comma separated list following `option` and parses every element
+fn list<T, I>(args: &mut I, option: &'static str) -> Result<Vec<T>, CliError>
+where
+    T: std::str::FromStr,
+    I: Iterator<Item = String>,
+{
+    let value = args.next().ok_or(CliError::MissingValue(option))?;
+    value
+        .split(',')
+        .map(|element| element.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| CliError::InvalidValue { option, value })
+}
+
+/// Executes a parsed command
+pub fn execute(command: Command) {
+    match command {
+        Command::List => {
+            for demo in demos::DEMOS {
+                let defaults = &demo.defaults;
+                println!(
+                    "{:<22} {} (threads {}, iterations {}, delay {} ms)",
+                    demo.name,
+                    demo.description,
+                    defaults.threads,
+                    defaults.iterations,
+                    defaults.delay.as_millis()
+                );
+            }
+        }
+        Command::Run { demo, config } => (demo.run)(&config, &RealClock::shared()),
+        Command::Bench {
+            workload,
+            threads,
+            config,
+            format,
+        } => {
+            let reports: Vec<_> = threads
+                .iter()
+                .map(|&threads| workload.compare(threads, &config))
+                .collect();
+            print_reports(&reports, format);
+        }
+        Command::ChannelBench {
+            batches,
+            capacity,
+            config,
+            format,
+        } => {
+            let reports = bench::channel_throughput(&batches, capacity, &config);
+            print_reports(&reports, format);
+        }
+        Command::CounterBench {
+            threads,
+            config,
+            format,
+        } => {
+            let reports: Vec<_> = threads
+                .iter()
+                .flat_map(|&threads| counter::compare(threads, &config))
+                .collect();
+            print_reports(&reports, format);
+        }
+        Command::FalseSharingBench {
+            threads,
+            config,
+            format,
+        } => {
+            let reports: Vec<_> = threads
+                .iter()
+                .map(|&threads| bench::false_sharing(threads, &config))
+                .collect();
+            print_reports(&reports, format);
+        }
+        Command::Litmus {
+            patterns,
+            orderings,
+            iterations,
+            batch,
+        } => {
+            for pattern in patterns {
+                for &ordering in &orderings {
+                    println!("{}", litmus::run(pattern, ordering, iterations, batch));
+                }
+            }
+        }
+        Command::Help => println!("{}", USAGE),
+    }
+}
+
+/// Prints bench reports in the requested format
+fn print_reports<R: Report>(reports: &[R], format: Format) {
+    match format {
+        Format::Table => print!("{}", bench::to_table(reports)),
+        Format::Json => println!("{}", bench::to_json(reports)),
+        Format::Csv => print!("{}", bench::to_csv(reports)),
+    }
+}
+
+/// Parses and executes the arguments that follow the program name
+pub fn run<I>(args: I) -> Result<(), CliError>
+where
+    I: IntoIterator<Item = String>,
+{
+    execute(parse(args)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn run_overrides_defaults() {
+        let command = parse(args("run mutex --threads 4 --iterations 100 --delay-ms 5")).unwrap();
+        match command {
+            Command::Run { demo, config } => {
+                assert_eq!(demo.name, "mutex");
+                assert_eq!(config, DemoConfig::new(4, 100, Duration::from_millis(5)));
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn run_keeps_defaults_for_missing_options() {
+        match parse(args("run parallel --threads 3")).unwrap() {
+            Command::Run { config, .. } => {
+                assert_eq!(config, DemoConfig::new(3, 6, Duration::from_secs(1)));
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bench_options() {
+        let command = parse(args(
+            "bench spin --threads 1,2,8 --units 32 --repetitions 3 --rounds 10 --format csv",
+        ))
+        .unwrap();
+        match command {
+            Command::Bench {
+                workload,
+                threads,
+                config,
+                format,
+            } => {
+                assert_eq!(workload, Workload::Spin(10));
+                assert_eq!(threads, vec![1, 2, 8]);
+                assert_eq!(config.units, 32);
+                assert_eq!(config.repetitions, 3);
+                assert_eq!(config.warmup, 1);
+                assert_eq!(format, Format::Csv);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        assert_eq!(
+            parse(args("bench sleep --threads 2,x")).unwrap_err(),
+            CliError::InvalidValue {
+                option: "--threads",
+                value: "2,x".into()
+            }
+        );
+        assert_eq!(
+            parse(args("bench spin --units 0")).unwrap_err(),
+            CliError::InvalidValue {
+                option: "--units",
+                value: "0".into()
+            }
+        );
+        assert_eq!(
+            parse(args("bench sleep --batch 4")).unwrap_err(),
+            CliError::UnknownOption("--batch".into())
+        );
+        assert_eq!(
+            parse(args("bench spin --delay-ms 1")).unwrap_err(),
+            CliError::UnknownOption("--delay-ms".into())
+        );
+        assert_eq!(
+            parse(args("bench disk")).unwrap_err(),
+            CliError::UnknownWorkload("disk".into())
+        );
+    }
+
+    #[test]
+    fn channel_bench_options() {
+        match parse(args(
+            "bench channel --batch 1,64 --messages 5000 --capacity 64 --format json",
+        ))
+        .unwrap()
+        {
+            Command::ChannelBench {
+                batches,
+                capacity,
+                config,
+                format,
+            } => {
+                assert_eq!(batches, vec![1, 64]);
+                assert_eq!(capacity, 64);
+                assert_eq!(config.units, 5000);
+                assert_eq!(format, Format::Json);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        assert_eq!(
+            parse(args("bench channel --batch 0")).unwrap_err(),
+            CliError::InvalidValue {
+                option: "--batch",
+                value: "0".into()
+            }
+        );
+        assert_eq!(
+            parse(args("bench channel --messages 0")).unwrap_err(),
+            CliError::InvalidValue {
+                option: "--messages",
+                value: "0".into()
+            }
+        );
+        assert_eq!(
+            parse(args("bench channel --threads 2")).unwrap_err(),
+            CliError::UnknownOption("--threads".into())
+        );
+    }
+
+    #[test]
+    fn counter_bench_options() {
+        match parse(args("bench counter --increments 500 --repetitions 2")).unwrap() {
+            Command::CounterBench {
+                threads,
+                config,
+                format,
+            } => {
+                assert_eq!(threads, vec![10]);
+                assert_eq!(config.units, 500);
+                assert_eq!(config.repetitions, 2);
+                assert_eq!(format, Format::Table);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        match parse(args("bench false-sharing --increments 500")).unwrap() {
+            Command::FalseSharingBench {
+                threads, config, ..
+            } => {
+                assert_eq!(threads, vec![2, 4]);
+                assert_eq!(config.units, 500);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        for workload in ["counter", "false-sharing"] {
+            assert_eq!(
+                parse(args(&format!("bench {} --increments 0", workload))).unwrap_err(),
+                CliError::InvalidValue {
+                    option: "--increments",
+                    value: "0".into()
+                }
+            );
+        }
+        assert_eq!(
+            parse(args("bench counter --messages 10")).unwrap_err(),
+            CliError::UnknownOption("--messages".into())
+        );
+        assert_eq!(
+            parse(args("bench false-sharing --units 4")).unwrap_err(),
+            CliError::UnknownOption("--units".into())
+        );
+        assert_eq!(
+            parse(args("bench counter --threads 4 --increments 1000000000")).unwrap_err(),
+            CliError::InvalidValue {
+                option: "--increments",
+                value: "1000000000".into()
+            }
+        );
+    }
+
+    #[test]
+    fn litmus_options() {
+        match parse(args(
+            "litmus sb iriw --ordering acqrel,seqcst --iterations 100",
+        ))
+        .unwrap()
+        {
+            Command::Litmus {
+                patterns,
+                orderings,
+                iterations,
+                batch,
+            } => {
+                assert_eq!(patterns, vec![Pattern::StoreBuffering, Pattern::Iriw]);
+                assert_eq!(orderings, vec![Ordering::AcqRel, Ordering::SeqCst]);
+                assert_eq!(iterations, 100);
+                assert_eq!(batch, 10_000);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        match parse(args("litmus")).unwrap() {
+            Command::Litmus {
+                patterns,
+                orderings,
+                ..
+            } => {
+                assert_eq!(patterns.len(), 4);
+                assert_eq!(orderings.len(), 5);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        assert_eq!(
+            parse(args("litmus --ordering consume")).unwrap_err(),
+            CliError::InvalidValue {
+                option: "--ordering",
+                value: "consume".into()
+            }
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse(args("")).unwrap_err(), CliError::MissingCommand);
+        assert_eq!(
+            parse(args("start")).unwrap_err(),
+            CliError::UnknownCommand("start".into())
+        );
+        assert_eq!(parse(args("run")).unwrap_err(), CliError::MissingDemo);
+        assert_eq!(
+            parse(args("run nope")).unwrap_err(),
+            CliError::UnknownDemo("nope".into())
+        );
+        assert_eq!(
+            parse(args("run mutex --threads")).unwrap_err(),
+            CliError::MissingValue("--threads")
+        );
+        assert_eq!(
+            parse(args("run mutex --threads many")).unwrap_err(),
+            CliError::InvalidValue {
+                option: "--threads",
+                value: "many".into()
+            }
+        );
+        assert_eq!(
+            parse(args("run thread-pool --threads 0")).unwrap_err(),
+            CliError::InvalidValue {
+                option: "--threads",
+                value: "0".into()
+            }
+        );
+        assert_eq!(
+            parse(args("run channel-multi-sender --iterations 5000000000")).unwrap_err(),
+            CliError::InvalidValue {
+                option: "--iterations",
+                value: "5000000000".into()
+            }
+        );
+        assert_eq!(
+            parse(args("run mutex --threads 3 --iterations 1000000000")).unwrap_err(),
+            CliError::InvalidValue {
+                option: "--iterations",
+                value: "1000000000".into()
+            }
+        );
+        assert_eq!(
+            parse(args("run mutex --fast")).unwrap_err(),
+            CliError::UnknownOption("--fast".into())
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! Time sources for the demos.
+//!
+//! [`RealClock`] sleeps for real and is what the command line uses.
+//! [`VirtualClock`] keeps its own notion of "now" and only moves it forward
+//! once every participating thread is asleep, which lets the same scenarios run
+//! in milliseconds under test and makes their timing fully predictable.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::join::{self, JoinHandle};
+
+/// A source of time that threads can read and sleep on
+pub trait Clock: Send + Sync {
+    /// Time elapsed since the clock was created
+    fn now(&self) -> Duration;
+
+    /// Blocks the calling thread for `duration` of this clock's time
+    fn sleep(&self, duration: Duration);
+
+    /// Registers one more thread taking part in timed work.
+    ///
+    /// A virtual clock only advances when every registered participant is
+    /// sleeping; real clocks ignore registration.
+    fn enter(&self) {}
+
+    /// Unregisters a thread previously counted by [`Clock::enter`]
+    fn leave(&self) {}
+}
+
+/// A clock shared between threads
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Keeps the calling thread registered with a clock until dropped
+pub struct Participation {
+    clock: SharedClock,
+}
+
+impl Drop for Participation {
+    fn drop(&mut self) {
+        self.clock.leave();
+    }
+}
+
+/// Registers the calling thread with `clock` for as long as the guard lives.
+///
+/// Every thread that sleeps on a [`VirtualClock`] must be a participant,
+/// otherwise time may advance while it is still running.
+pub fn participate(clock: &SharedClock) -> Participation {
+    clock.enter();
+    Participation {
+        clock: Arc::clone(clock),
+    }
+}
+
+/// Spawns a thread that participates in `clock` for its whole lifetime.
+///
+/// The thread is registered before it is spawned, so the clock cannot advance
+/// in the window between `spawn` returning and the thread starting to run.
+/// When spawning several threads, hold a [`participate`] guard on the spawning
+/// thread until all of them exist, or the first ones may run ahead in time.
+pub fn spawn<F, T>(clock: &SharedClock, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let participation = participate(clock);
+    join::spawn(move || {
+        let _participation = participation;
+        f()
+    })
+}
+
+/// Wall-clock time backed by `std::thread::sleep`
+pub struct RealClock {
+    start: Instant,
+}
+
+impl RealClock {
+    /// Creates a clock whose `now` starts at zero
+    pub fn new() -> Self {
+        RealClock {
+            start: Instant::now(),
+        }
+    }
+
+    /// Creates a clock ready to be shared between threads
+    pub fn shared() -> SharedClock {
+        Arc::new(RealClock::new())
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> Self {
+        RealClock::new()
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Deterministic time that jumps forward when all participants are asleep.
+///
+/// When the last awake participant calls [`Clock::sleep`] (or leaves), the
+/// clock jumps to the earliest pending deadline and wakes exactly the threads
+/// waiting for it. A participant that blocks on anything other than this
+/// clock (a channel, a join) therefore stops time for everybody.
+pub struct VirtualClock {
+    state: Mutex<VirtualState>,
+    wakeup: Condvar,
+}
+
+struct VirtualState {
+    now: Duration,
+    participants: usize,
+    sleeping: usize,
+    deadlines: BinaryHeap<Reverse<Duration>>,
+}
+
+impl VirtualClock {
+    /// Creates a clock whose `now` starts at zero
+    pub fn new() -> Self {
+        VirtualClock {
+            state: Mutex::new(VirtualState {
+                now: Duration::ZERO,
+                participants: 0,
+                sleeping: 0,
+                deadlines: BinaryHeap::new(),
+            }),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    /// Creates a clock ready to be shared between threads
+    pub fn shared() -> SharedClock {
+        Arc::new(VirtualClock::new())
+    }
+
+    /// Moves time to the earliest deadline if nobody is awake any more
+    fn advance(&self, state: &mut VirtualState) {
+        if state.sleeping == 0 || state.sleeping < state.participants {
+            return;
+        }
+        if let Some(&Reverse(deadline)) = state.deadlines.peek() {
+            state.now = state.now.max(deadline);
+        }
+        // Every sleeper whose deadline has been reached is awake again
+        while let Some(&Reverse(deadline)) = state.deadlines.peek() {
+            if deadline > state.now {
+                break;
+            }
+            state.deadlines.pop();
+            state.sleeping -= 1;
+        }
+        self.wakeup.notify_all();
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        VirtualClock::new()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        self.state.lock().unwrap().now
+    }
+
+    fn sleep(&self, duration: Duration) {
+        if duration.is_zero() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        // A sleep past the end of time ends with the clock at `Duration::MAX`
+        let deadline = state.now.saturating_add(duration);
+        state.deadlines.push(Reverse(deadline));
+        state.sleeping += 1;
+        self.advance(&mut state);
+        while state.now < deadline {
+            state = self.wakeup.wait(state).unwrap();
+        }
+    }
+
+    fn enter(&self) {
+        self.state.lock().unwrap().participants += 1;
+    }
+
+    fn leave(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.participants -= 1;
+        self.advance(&mut state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virtual_sleep_does_not_take_real_time() {
+        let clock = VirtualClock::shared();
+        let started = Instant::now();
+        let _participation = participate(&clock);
+        clock.sleep(Duration::from_secs(3600));
+        assert_eq!(clock.now(), Duration::from_secs(3600));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        clock.sleep(Duration::MAX);
+        assert_eq!(clock.now(), Duration::MAX);
+    }
+
+    #[test]
+    fn virtual_clock_waits_for_every_participant() {
+        let clock = VirtualClock::shared();
+        let setup = participate(&clock);
+        let fast = spawn(&clock, {
+            let clock = Arc::clone(&clock);
+            move || {
+                clock.sleep(Duration::from_secs(1));
+                clock.now()
+            }
+        });
+        let slow = spawn(&clock, {
+            let clock = Arc::clone(&clock);
+            move || {
+                clock.sleep(Duration::from_secs(5));
+                clock.now()
+            }
+        });
+        drop(setup);
+        assert_eq!(fast.join().unwrap(), Duration::from_secs(1));
+        assert_eq!(slow.join().unwrap(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn parallel_sleepers_overlap() {
+        let clock = VirtualClock::shared();
+        let setup = participate(&clock);
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let clock_clone = Arc::clone(&clock);
+                spawn(&clock, move || {
+                    for _ in 0..3 {
+                        clock_clone.sleep(Duration::from_secs(2));
+                    }
+                })
+            })
+            .collect();
+        drop(setup);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        // Four threads sleeping 3 x 2 seconds side by side take 6 seconds, not 24
+        assert_eq!(clock.now(), Duration::from_secs(6));
+    }
+
+    #[test]
+    fn real_clock_sleeps() {
+        let clock = RealClock::new();
+        clock.sleep(Duration::from_millis(20));
+        assert!(clock.now() >= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,519 @@
+//! One increment workload, several ways of sharing the counter.
+//!
+//! The `race-condition`, `atomic`, `atomic-arc` and `mutex` demos each run
+//! the same workload, N threads adding 1 M times, with their own counter.
+//! Here each strategy is a [`Counter`] and [`run`] executes the workload for
+//! any of them: it checks the total against `threads * increments`, counts
+//! the lost updates and times the runs. [`STRATEGIES`] lists the built-in
+//! counters in the order they are reported.
+//!
+//! [`LockCounter`] runs the `mutex` workload with any [`Lock`], so the
+//! spinlocks of [`crate::lock`] are compared with `std::sync::Mutex` here.
+//!
+//! Every thread of the single-atomic and lock counters writes the same cache
+//! line. [`ShardedCounter`] gives each thread a slot on a line of its own and
+//! only adds the slots up when the total is read.
+
+use std::fmt;
+use std::sync::atomic::{AtomicI32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::bench::{self, BenchConfig, Column, Report, Stats, Value};
+use crate::cache_padded::CachePadded;
+use crate::lock::{BackoffLock, Lock, TasLock, TtasLock};
+
+/// A counter shared by every thread of the workload
+pub trait Counter: Send + Sync {
+    /// Adds one
+    fn increment(&self);
+
+    /// The current total
+    fn get(&self) -> u64;
+}
+
+/// Unsynchronized read-modify-write, like `COUNTER += 1` on a `static mut`.
+///
+/// The load and the store are separate atomic operations, so the lost updates
+/// of the `race-condition` demo happen here too, without its undefined behaviour.
+#[derive(Debug, Default)]
+pub struct RacyCounter {
+    value: AtomicI32,
+}
+
+impl Counter for RacyCounter {
+    fn increment(&self) {
+        let value = self.value.load(Ordering::Relaxed);
+        self.value.store(value.wrapping_add(1), Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed) as u32 as u64
+    }
+}
+
+/// An `AtomicI32` with a `'static` lifetime, like `COUNTER_NEW` in the `atomic` demo.
+///
+/// Every counter is leaked, so each run starts from zero without any
+/// reference counting.
+#[derive(Debug)]
+pub struct StaticAtomicCounter {
+    value: &'static AtomicI32,
+}
+
+impl StaticAtomicCounter {
+    /// Leaks a new zeroed atomic
+    pub fn new() -> Self {
+        StaticAtomicCounter {
+            value: Box::leak(Box::new(AtomicI32::new(0))),
+        }
+    }
+}
+
+impl Default for StaticAtomicCounter {
+    fn default() -> Self {
+        StaticAtomicCounter::new()
+    }
+}
+
+impl Counter for StaticAtomicCounter {
+    fn increment(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed) as u32 as u64
+    }
+}
+
+/// A reference-counted `AtomicI32`, like the `atomic-arc` demo
+#[derive(Debug, Default)]
+pub struct ArcAtomicCounter {
+    value: Arc<AtomicI32>,
+}
+
+impl Counter for ArcAtomicCounter {
+    fn increment(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed) as u32 as u64
+    }
+}
+
+/// An `i32` behind a reference-counted `Mutex`, like the `mutex` demo
+#[derive(Debug, Default)]
+pub struct MutexCounter {
+    value: Arc<Mutex<i32>>,
+}
+
+impl Counter for MutexCounter {
+    fn increment(&self) {
+        let mut value = self.value.lock().unwrap();
+        *value = value.wrapping_add(1);
+    }
+
+    fn get(&self) -> u64 {
+        *self.value.lock().unwrap() as u32 as u64
+    }
+}
+
+/// An `i32` behind any [`Lock`], the `mutex` demo with the lock swapped out
+#[derive(Debug, Default)]
+pub struct LockCounter<L> {
+    value: L,
+}
+
+impl<L: Lock<i32>> Counter for LockCounter<L> {
+    fn increment(&self) {
+        let mut value = self.value.lock();
+        *value = value.wrapping_add(1);
+    }
+
+    fn get(&self) -> u64 {
+        *self.value.lock() as u32 as u64
+    }
+}
+
+/// Set in a shard while [`ShardedCounter::snapshot`] holds it
+const SHARD_LOCKED: u64 = 1 << 63;
+
+/// Hands every thread the next shard index
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Shard this thread increments, modulo the number of shards
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A counter split into cache-padded shards, one per thread modulo the shard count.
+///
+/// An increment only touches the slot of the calling thread, so threads on
+/// different shards never contend. [`sum`](ShardedCounter::sum) adds the
+/// shards up without stopping anybody: the result lies between the totals at
+/// its start and its end. [`snapshot`](ShardedCounter::snapshot) locks every
+/// shard before adding them up and returns the exact total at one instant,
+/// making incrementers wait meanwhile.
+#[derive(Debug)]
+pub struct ShardedCounter {
+    shards: Box<[CachePadded<AtomicU64>]>,
+    /// Keeps two snapshots from locking shards against each other
+    snapshot: Mutex<()>,
+}
+
+impl ShardedCounter {
+    /// Creates a zeroed counter with `shards` slots
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn new(shards: usize) -> Self {
+        assert!(shards > 0, "a sharded counter needs one shard");
+        ShardedCounter {
+            shards: (0..shards)
+                .map(|_| CachePadded::new(AtomicU64::new(0)))
+                .collect(),
+            snapshot: Mutex::new(()),
+        }
+    }
+
+    /// Number of shards
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Adds one to the shard of the calling thread
+    pub fn increment(&self) {
+        let shard = &self.shards[SHARD.with(|shard| *shard) % self.shards.len()];
+        let mut value = shard.load(Ordering::Relaxed);
+        loop {
+            if value & SHARD_LOCKED != 0 {
+                thread::yield_now();
+                value = shard.load(Ordering::Relaxed);
+                continue;
+            }
+            match shard.compare_exchange_weak(
+                value,
+                value + 1,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(current) => value = current,
+            }
+        }
+    }
+
+    /// Adds the shards up without blocking incrementers
+    pub fn sum(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.load(Ordering::Acquire) & !SHARD_LOCKED)
+            .sum()
+    }
+
+    /// The exact total at one instant; increments wait while it is taken
+    pub fn snapshot(&self) -> u64 {
+        let _exclusive = self.snapshot.lock().unwrap();
+        for shard in self.shards.iter() {
+            shard.fetch_or(SHARD_LOCKED, Ordering::Acquire);
+        }
+        // Every shard is frozen now, so the sum is the total of this instant
+        let total = self.sum();
+        for shard in self.shards.iter() {
+            shard.fetch_and(!SHARD_LOCKED, Ordering::Release);
+        }
+        total
+    }
+}
+
+impl Default for ShardedCounter {
+    /// Four shards per hardware thread, so a few more threads than cores rarely share one
+    fn default() -> Self {
+        let parallelism = thread::available_parallelism().map_or(1, |count| count.get());
+        ShardedCounter::new((parallelism * 4).max(8))
+    }
+}
+
+impl Counter for ShardedCounter {
+    fn increment(&self) {
+        ShardedCounter::increment(self);
+    }
+
+    fn get(&self) -> u64 {
+        self.sum()
+    }
+}
+
+/// An entry of the strategy registry
+pub struct Strategy {
+    /// Name used in reports
+    pub name: &'static str,
+    /// One line description
+    pub description: &'static str,
+    /// Creates a zeroed counter
+    pub new: fn() -> Arc<dyn Counter>,
+}
+
+impl fmt::Debug for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Strategy")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Every built-in counter, in the order they are reported
+pub const STRATEGIES: &[Strategy] = &[
+    Strategy {
+        name: "racy",
+        description: "separate load and store, loses updates",
+        new: || Arc::new(RacyCounter::default()),
+    },
+    Strategy {
+        name: "static-atomic",
+        description: "`fetch_add` on a `'static` `AtomicI32`",
+        new: || Arc::new(StaticAtomicCounter::new()),
+    },
+    Strategy {
+        name: "arc-atomic",
+        description: "`fetch_add` on an `Arc<AtomicI32>`",
+        new: || Arc::new(ArcAtomicCounter::default()),
+    },
+    Strategy {
+        name: "mutex",
+        description: "`+= 1` under an `Arc<Mutex<i32>>`",
+        new: || Arc::new(MutexCounter::default()),
+    },
+    Strategy {
+        name: "tas",
+        description: "an `i32` behind a test-and-set spinlock",
+        new: || Arc::new(LockCounter::<TasLock<i32>>::default()),
+    },
+    Strategy {
+        name: "ttas",
+        description: "an `i32` behind a test-and-test-and-set spinlock",
+        new: || Arc::new(LockCounter::<TtasLock<i32>>::default()),
+    },
+    Strategy {
+        name: "backoff",
+        description: "an `i32` behind a spinlock backing off, then yielding",
+        new: || Arc::new(LockCounter::<BackoffLock<i32>>::default()),
+    },
+    Strategy {
+        name: "sharded",
+        description: "one cache-padded `AtomicU64` per thread, summed on read",
+        new: || Arc::new(ShardedCounter::default()),
+    },
+];
+
+/// Looks a strategy up by name
+pub fn find(name: &str) -> Option<&'static Strategy> {
+    STRATEGIES.iter().find(|strategy| strategy.name == name)
+}
+
+/// Outcome of the workload for one strategy
+#[derive(Debug, Clone, PartialEq)]
+pub struct CounterReport {
+    pub name: &'static str,
+    pub threads: usize,
+    /// Increments per thread
+    pub increments: usize,
+    pub repetitions: usize,
+    /// Updates lost in the worst repetition
+    pub lost: u64,
+    /// Repetitions that ended with the expected total
+    pub exact_runs: usize,
+    pub time: Stats,
+}
+
+impl CounterReport {
+    /// The total every repetition should reach
+    pub fn expected(&self) -> u64 {
+        (self.threads * self.increments) as u64
+    }
+
+    /// Whether every repetition reached the expected total
+    pub fn is_exact(&self) -> bool {
+        self.exact_runs == self.repetitions
+    }
+
+    /// Increments per second of the median repetition, lost ones included
+    pub fn increments_per_sec(&self) -> f64 {
+        self.expected() as f64 / self.time.median.as_secs_f64()
+    }
+}
+
+/// Runs `threads` threads each incrementing a fresh counter `config.units` times,
+/// once per warm-up round and repetition
+pub fn run(strategy: &Strategy, threads: usize, config: &BenchConfig) -> CounterReport {
+    assert!(threads > 0, "at least one thread is needed");
+    assert!(config.repetitions > 0, "at least one repetition is needed");
+    let increments = config.units;
+    let expected = (threads * increments) as u64;
+    let mut totals = Vec::with_capacity(config.warmup + config.repetitions);
+    let time = bench::measure(config, || {
+        let counter = (strategy.new)();
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || {
+                    for _ in 0..increments {
+                        counter.increment();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        totals.push(counter.get());
+    });
+    let timed = &totals[config.warmup..];
+    CounterReport {
+        name: strategy.name,
+        threads,
+        increments,
+        repetitions: config.repetitions,
+        lost: timed
+            .iter()
+            .map(|&total| expected.saturating_sub(total))
+            .max()
+            .unwrap_or(0),
+        exact_runs: timed.iter().filter(|&&total| total == expected).count(),
+        time,
+    }
+}
+
+/// Runs the workload for every built-in strategy
+pub fn compare(threads: usize, config: &BenchConfig) -> Vec<CounterReport> {
+    STRATEGIES
+        .iter()
+        .map(|strategy| run(strategy, threads, config))
+        .collect()
+}
+
+impl Report for CounterReport {
+    const COLUMNS: &'static [Column] = &[
+        Column::left("counter", 14),
+        Column::right("threads", 7),
+        Column::right("expected", 12),
+        Column::right("worst lost", 12),
+        Column::right("exact", 10),
+        Column::right("median", 12),
+        Column::right("p95", 12),
+        Column::right("increments/s", 14),
+    ];
+
+    fn cells(&self, _: &[Self]) -> Vec<String> {
+        vec![
+            self.name.to_string(),
+            self.threads.to_string(),
+            self.expected().to_string(),
+            self.lost.to_string(),
+            format!("{}/{}", self.exact_runs, self.repetitions),
+            format!("{:.2?}", self.time.median),
+            format!("{:.2?}", self.time.p95),
+            format!("{:.0}", self.increments_per_sec()),
+        ]
+    }
+
+    fn fields(&self, _: &[Self]) -> Vec<(&'static str, Value<'_>)> {
+        vec![
+            ("counter", self.name.into()),
+            ("threads", self.threads.into()),
+            ("increments", self.increments.into()),
+            ("repetitions", self.repetitions.into()),
+            ("expected", self.expected().into()),
+            ("lost", self.lost.into()),
+            ("exact_runs", self.exact_runs.into()),
+            ("time", self.time.into()),
+            ("increments_per_sec", self.increments_per_sec().into()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(increments: usize) -> BenchConfig {
+        BenchConfig {
+            units: increments,
+            warmup: 0,
+            repetitions: 2,
+        }
+    }
+
+    #[test]
+    fn synchronized_counters_are_exact() {
+        for name in [
+            "static-atomic",
+            "arc-atomic",
+            "mutex",
+            "tas",
+            "ttas",
+            "backoff",
+            "sharded",
+        ] {
+            let report = run(find(name).unwrap(), 4, &config(10_000));
+            assert_eq!(report.expected(), 40_000);
+            assert!(report.is_exact(), "{} lost {} updates", name, report.lost);
+            assert_eq!(report.lost, 0);
+        }
+    }
+
+    #[test]
+    fn racy_counter_never_gains_updates() {
+        let report = run(find("racy").unwrap(), 4, &config(100_000));
+        assert!(report.lost <= report.expected());
+        // A single thread has nobody to race with
+        assert!(run(find("racy").unwrap(), 1, &config(10_000)).is_exact());
+    }
+
+    #[test]
+    fn compare_reports_every_strategy() {
+        let reports = compare(2, &config(1_000));
+        let names: Vec<_> = reports.iter().map(|report| report.name).collect();
+        assert_eq!(
+            names,
+            [
+                "racy",
+                "static-atomic",
+                "arc-atomic",
+                "mutex",
+                "tas",
+                "ttas",
+                "backoff",
+                "sharded"
+            ]
+        );
+        assert_eq!(bench::to_csv(&reports).lines().count(), 9);
+        assert!(bench::to_json(&reports).starts_with("[{\"counter\":\"racy\",\"threads\":2,"));
+        assert!(bench::to_table(&reports).contains("2/2"));
+    }
+
+    #[test]
+    fn sharded_snapshots_are_exact_and_monotonic() {
+        let counter = ShardedCounter::new(4);
+        assert_eq!(counter.shards(), 4);
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..20_000 {
+                        counter.increment();
+                    }
+                });
+            }
+            let mut previous = 0;
+            for _ in 0..100 {
+                let snapshot = counter.snapshot();
+                assert!(snapshot >= previous && snapshot <= 80_000);
+                previous = snapshot;
+            }
+        });
+        assert_eq!(counter.snapshot(), 80_000);
+        assert_eq!(counter.sum(), 80_000);
+    }
+}
@@ -0,0 +1,116 @@
+//! Waiting on a `Condvar` until an optional deadline.
+//!
+//! Blocking calls that take a timeout turn it into a deadline with [`after`]
+//! and wait with [`wait_while`]. A timeout too long for an `Instant`, such as
+//! `Duration::MAX`, gives no deadline at all, so the call waits like its
+//! untimed variant instead of panicking, as `std::sync::mpsc` does.
+//!
+//! [`recv_until`] is the receive loop of the channels built on a `Mutex` and
+//! a `Condvar`, shared by their `recv` and `recv_timeout`.
+
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Condvar, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// The instant `timeout` from now, `None` if it cannot be represented
+pub(crate) fn after(timeout: Duration) -> Option<Instant> {
+    Instant::now().checked_add(timeout)
+}
+
+/// Waits on `condvar` while `condition` holds, giving up at `deadline`.
+///
+/// Returns the guard and whether the deadline passed with `condition` still
+/// holding.
+pub(crate) fn wait_while<'a, T>(
+    condvar: &Condvar,
+    mut guard: MutexGuard<'a, T>,
+    deadline: Option<Instant>,
+    mut condition: impl FnMut(&mut T) -> bool,
+) -> (MutexGuard<'a, T>, bool) {
+    while condition(&mut guard) {
+        guard = match wait(condvar, guard, deadline) {
+            Ok(guard) => guard,
+            Err(guard) => return (guard, true),
+        };
+    }
+    (guard, false)
+}
+
+/// Takes a message with `take`, waiting on `condvar` until there is one.
+///
+/// Fails once `disconnected` says no message can arrive anymore, or when
+/// `deadline` passes. `take` runs under the lock every time the condvar is
+/// signalled, so it also decides what counts as a message: one value, a
+/// batch, or nothing if every queued value has expired.
+pub(crate) fn recv_until<S, T>(
+    condvar: &Condvar,
+    mut state: MutexGuard<'_, S>,
+    deadline: Option<Instant>,
+    mut take: impl FnMut(&mut S) -> Option<T>,
+    disconnected: impl Fn(&S) -> bool,
+) -> Result<T, RecvTimeoutError> {
+    loop {
+        if let Some(value) = take(&mut state) {
+            return Ok(value);
+        }
+        if disconnected(&state) {
+            return Err(RecvTimeoutError::Disconnected);
+        }
+        state = wait(condvar, state, deadline).map_err(|_| RecvTimeoutError::Timeout)?;
+    }
+}
+
+/// Waits for one signal, giving the guard back as `Err` if `deadline` has passed
+pub(crate) fn wait<'a, T>(
+    condvar: &Condvar,
+    guard: MutexGuard<'a, T>,
+    deadline: Option<Instant>,
+) -> Result<MutexGuard<'a, T>, MutexGuard<'a, T>> {
+    let Some(deadline) = deadline else {
+        return Ok(condvar.wait(guard).unwrap());
+    };
+    let now = Instant::now();
+    if now >= deadline {
+        return Err(guard);
+    }
+    Ok(condvar.wait_timeout(guard, deadline - now).unwrap().0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    #[test]
+    fn huge_timeouts_have_no_deadline() {
+        assert!(after(Duration::MAX).is_none());
+        assert!(after(Duration::from_secs(1)).unwrap() > Instant::now());
+    }
+
+    #[test]
+    fn waits_until_the_condition_or_the_deadline() {
+        let shared = Arc::new((Mutex::new(false), Condvar::new()));
+        let (lock, condvar) = &*shared;
+        let (ready, timed_out) = wait_while(
+            condvar,
+            lock.lock().unwrap(),
+            after(Duration::from_millis(10)),
+            |ready| !*ready,
+        );
+        assert!(!*ready && timed_out);
+        drop(ready);
+
+        let setter = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                *shared.0.lock().unwrap() = true;
+                shared.1.notify_all();
+            })
+        };
+        let (ready, timed_out) = wait_while(condvar, lock.lock().unwrap(), None, |ready| !*ready);
+        assert!(*ready && !timed_out);
+        drop(ready);
+        setter.join().unwrap();
+    }
+}
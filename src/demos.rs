@@ -0,0 +1,1311 @@
+//! The concurrency experiments of this repository, parameterized so they can be
+//! run from the command line as well as from `cargo test`.
+//!
+//! Every pause goes through a [`Clock`](crate::clock::Clock): the command line
+//! passes a real clock, the tests a virtual one so they finish in milliseconds.
+
+use std::fmt;
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cancel::{self, CancellationToken, JoinTimeoutError};
+use crate::channel::broadcast;
+use crate::channel::control::{self, Message};
+use crate::channel::select::Select;
+use crate::channel::tagged::{self, OrderChecker, OrderReport, Tagged};
+use crate::channel::{mpmc, oneshot, watch};
+use crate::clock::{self, Clock, SharedClock};
+use crate::pool::ThreadPool;
+
+/// Parameters shared by every demo.
+///
+/// Each demo interprets the fields in its own way (number of workers, loop
+/// length, pause between steps); see [`Demo::defaults`] for the values the
+/// original experiments were written with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DemoConfig {
+    /// Number of threads (workers, senders, ...) the demo spawns
+    pub threads: usize,
+    /// Number of steps (loop iterations, messages, increments) per thread
+    pub iterations: usize,
+    /// Pause between two steps
+    pub delay: Duration,
+}
+
+impl DemoConfig {
+    /// Creates a configuration from its three parameters
+    pub const fn new(threads: usize, iterations: usize, delay: Duration) -> Self {
+        DemoConfig {
+            threads,
+            iterations,
+            delay,
+        }
+    }
+
+    /// `delay` times `steps`, or `None` if that does not fit in a `Duration`
+    pub fn delay_times(&self, steps: usize) -> Option<Duration> {
+        u32::try_from(steps)
+            .ok()
+            .and_then(|steps| self.delay.checked_mul(steps))
+    }
+}
+
+/// A runnable entry of the demo registry
+pub struct Demo {
+    /// Name used on the command line, e.g. `channel-queue`
+    pub name: &'static str,
+    /// One line description shown by `list`
+    pub description: &'static str,
+    /// Parameters the experiment was originally written with
+    pub defaults: DemoConfig,
+    /// Runs the demo with the given parameters
+    pub run: fn(&DemoConfig, &SharedClock),
+}
+
+impl fmt::Debug for Demo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Demo")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Every demo known to the CLI, in the order they are listed
+pub const DEMOS: &[Demo] = &[
+    Demo {
+        name: "threaded",
+        description: "spawn threads that print while the main thread sleeps",
+        defaults: DemoConfig::new(1, 6, Duration::from_secs(1)),
+        run: threaded,
+    },
+    Demo {
+        name: "join-threads",
+        description: "capture a value returned by a thread through join",
+        defaults: DemoConfig::new(1, 6, Duration::from_secs(1)),
+        run: |config, clock| {
+            join_threads(config, clock);
+        },
+    },
+    Demo {
+        name: "sequential",
+        description: "run calculate_counter several times on the main thread",
+        defaults: DemoConfig::new(2, 6, Duration::from_secs(1)),
+        run: |config, clock| {
+            sequential_process(config, clock);
+        },
+    },
+    Demo {
+        name: "parallel",
+        description: "run calculate_counter on several threads at once",
+        defaults: DemoConfig::new(2, 6, Duration::from_secs(1)),
+        run: |config, clock| {
+            parallel_process(config, clock);
+        },
+    },
+    Demo {
+        name: "thread-pool",
+        description: "run one-step jobs on a fixed pool of worker threads",
+        defaults: DemoConfig::new(2, 6, Duration::from_secs(1)),
+        run: |config, clock| {
+            thread_pool(config, clock);
+        },
+    },
+    Demo {
+        name: "cancellable",
+        description: "stop counting threads that overrun half of their time budget",
+        defaults: DemoConfig::new(2, 6, Duration::from_secs(1)),
+        run: |config, _clock| {
+            cancellable(config);
+        },
+    },
+    Demo {
+        name: "closure-move",
+        description: "move ownership of captured data into a thread",
+        defaults: DemoConfig::new(1, 1, Duration::from_secs(2)),
+        run: closure_using_move,
+    },
+    Demo {
+        name: "channel",
+        description: "pass a message from one thread to another",
+        defaults: DemoConfig::new(1, 1, Duration::from_secs(2)),
+        run: |config, clock| {
+            channel(config, clock);
+        },
+    },
+    Demo {
+        name: "channel-oneshot",
+        description: "hand a single result back through a oneshot channel",
+        defaults: DemoConfig::new(1, 1, Duration::from_secs(2)),
+        run: |config, clock| {
+            channel_oneshot(config, clock);
+        },
+    },
+    Demo {
+        name: "channel-queue",
+        description: "queue several messages and close the channel when done",
+        defaults: DemoConfig::new(1, 5, Duration::from_secs(2)),
+        run: |config, clock| {
+            channel_queue(config, clock);
+        },
+    },
+    Demo {
+        name: "channel-iterator",
+        description: "consume a channel through its iterator",
+        defaults: DemoConfig::new(1, 5, Duration::from_secs(2)),
+        run: |config, clock| {
+            channel_iterator(config, clock);
+        },
+    },
+    Demo {
+        name: "channel-multi-sender",
+        description: "several cloned senders feeding one receiver",
+        defaults: DemoConfig::new(2, 5, Duration::from_secs(1)),
+        run: |config, clock| {
+            channel_multi_sender(config, clock);
+        },
+    },
+    Demo {
+        name: "channel-select",
+        description: "wait on one receiver per sender with `select`",
+        defaults: DemoConfig::new(2, 5, Duration::from_secs(1)),
+        run: |config, clock| {
+            channel_select(config, clock);
+        },
+    },
+    Demo {
+        name: "channel-broadcast",
+        description: "deliver every message to several subscribers",
+        defaults: DemoConfig::new(3, 5, Duration::from_secs(2)),
+        run: |config, clock| {
+            channel_broadcast(config, clock);
+        },
+    },
+    Demo {
+        name: "channel-work-queue",
+        description: "several consumers draining one MPMC work queue",
+        defaults: DemoConfig::new(2, 6, Duration::from_secs(1)),
+        run: |config, clock| {
+            channel_work_queue(config, clock);
+        },
+    },
+    Demo {
+        name: "channel-watch",
+        description: "watchers following the latest published value",
+        defaults: DemoConfig::new(2, 5, Duration::from_secs(1)),
+        run: |config, clock| {
+            channel_watch(config, clock);
+        },
+    },
+    Demo {
+        name: "race-condition",
+        description: "increment a `static mut` counter without synchronization",
+        defaults: DemoConfig::new(10, 1_000_000, Duration::ZERO),
+        run: |config, _clock| {
+            race_condition(config);
+        },
+    },
+    Demo {
+        name: "atomic",
+        description: "increment a static `AtomicI32` counter",
+        defaults: DemoConfig::new(10, 1_000_000, Duration::ZERO),
+        run: |config, _clock| {
+            atomic(config);
+        },
+    },
+    Demo {
+        name: "atomic-arc",
+        description: "increment an `Arc<AtomicI32>` counter",
+        defaults: DemoConfig::new(10, 1_000_000, Duration::ZERO),
+        run: |config, _clock| {
+            atomic_arc(config);
+        },
+    },
+    Demo {
+        name: "mutex",
+        description: "increment an `Arc<Mutex<i32>>` counter",
+        defaults: DemoConfig::new(10, 1_000_000, Duration::ZERO),
+        run: |config, _clock| {
+            mutex(config);
+        },
+    },
+];
+
+/// Looks a demo up by its command line name
+pub fn find(name: &str) -> Option<&'static Demo> {
+    DEMOS.iter().find(|demo| demo.name == name)
+}
+
+/// Spawns threads that print while the main thread sleeps instead of joining them
+pub fn threaded(config: &DemoConfig, clock: &SharedClock) {
+    // Take part in the clock while spawning, the main thread sleeps on it below
+    let _participation = clock::participate(clock);
+    for id in 0..config.threads {
+        let iterations = config.iterations;
+        let delay = config.delay;
+        let thread_clock = Arc::clone(clock);
+        // Spawns a new thread
+        clock::spawn(clock, move || {
+            // Loop over the configured number of steps
+            for i in 0..iterations {
+                // Print a message from the spawned thread
+                println!("hi number {} from the spawned thread {}!", i, id);
+                // Pause execution between steps
+                thread_clock.sleep(delay);
+            }
+        });
+    }
+
+    // Prints a message indicating that the main thread is joining
+    println!("the main thread is being joined");
+
+    // Sleep the main thread long enough to allow the spawned threads to complete
+    clock.sleep(
+        config
+            .delay_times(config.iterations)
+            .expect("delay * iterations overflows a Duration"),
+    );
+}
+
+/*For the code below, I want to simulate the use of thread join in Rust. The join function is used to capture the returned result from a running thread's computation or value. Additionally, I will try to compare the execution time between running the function using threads and running it without using threads.*/
+
+/// Captures the value computed by a spawned thread through `join`
+pub fn join_threads(config: &DemoConfig, clock: &SharedClock) -> Option<i32> {
+    let iterations = config.iterations;
+    let delay = config.delay;
+    let thread_clock = Arc::clone(clock);
+    // Spawns a new thread and returns a handle
+    let handle = clock::spawn(clock, move || {
+        let mut counter = 0;
+        // Loop over the configured number of steps
+        for i in 0..iterations {
+            // Print the current counter value
+            println!("counter: {}", i);
+            // Pause execution between steps
+            thread_clock.sleep(delay);
+            // Increment the counter
+            counter += 1;
+        }
+        // Return the final counter value
+        counter
+    });
+
+    // Print a message indicating that the main thread is waiting for the spawned thread
+    println!("waiting for spawned thread");
+
+    // Wait for the spawned thread to complete and capture its result
+    let result = handle.join();
+
+    // Match the result of joining the thread
+    let counter = match result {
+        // If successful, print the final counter value
+        Ok(counter) => {
+            println!("the result is {}", counter);
+            Some(counter)
+        }
+        // If the thread panicked, print where and why
+        Err(error) => {
+            println!("the result is {}", error);
+            None
+        }
+    };
+
+    // Prints a message indicating that the main thread is being joined
+    println!("the main thread is being joined");
+    counter
+}
+
+/// Function to calculate a counter with a delay
+pub fn calculate_counter(iterations: usize, delay: Duration, clock: &dyn Clock) -> i32 {
+    let mut counter = 0;
+    // Loop over the configured number of steps
+    for i in 0..iterations {
+        // Print the current counter value
+        println!("counter: {}", i);
+        // Pause execution between steps
+        clock.sleep(delay);
+        // Increment the counter
+        counter += 1;
+    }
+    // Return the final counter value
+    counter
+}
+
+/// Runs `calculate_counter` once per configured thread, one after the other
+pub fn sequential_process(config: &DemoConfig, clock: &SharedClock) -> Vec<i32> {
+    // The main thread does all the sleeping itself
+    let _participation = clock::participate(clock);
+
+    // Runs calculate_counter sequentially
+    let results: Vec<i32> = (0..config.threads)
+        .map(|_| calculate_counter(config.iterations, config.delay, clock.as_ref()))
+        .collect();
+
+    // Prints the results
+    for (index, result) in results.iter().enumerate() {
+        println!("Total counter {}: {}", index + 1, result);
+    }
+    println!("Application finished!");
+
+    // With the defaults this takes 12 seconds of clock time; measure the real
+    // difference with `cargo run -- bench sleep`
+    results
+}
+
+/// Runs `calculate_counter` on every configured thread concurrently
+pub fn parallel_process(config: &DemoConfig, clock: &SharedClock) -> Vec<i32> {
+    // Hold the clock still until every thread has been spawned
+    let setup = clock::participate(clock);
+    // Spawns threads that run calculate_counter concurrently
+    let handles: Vec<_> = (0..config.threads)
+        .map(|_| {
+            let iterations = config.iterations;
+            let delay = config.delay;
+            let thread_clock = Arc::clone(clock);
+            clock::spawn(clock, move || {
+                calculate_counter(iterations, delay, thread_clock.as_ref())
+            })
+        })
+        .collect();
+    drop(setup);
+
+    // Print waiting message
+    println!("waiting for calculation . . . ");
+
+    // Wait for every thread to complete and match its result
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        match handle.join() {
+            Ok(result) => {
+                println!("the result is {}", result);
+                results.push(result);
+            }
+            // The typed JoinError names the thread, the location and the panic message
+            Err(error) => println!("the result is {}", error),
+        }
+    }
+
+    // Print Application finished message
+    println!("Application finished!");
+
+    // With the defaults this takes 6 seconds of clock time, half of
+    // `sequential_process`; see `bench` for measured speedups
+    results
+}
+
+/// Runs `iterations` jobs on a pool of `threads` workers instead of one thread per job
+pub fn thread_pool(config: &DemoConfig, clock: &SharedClock) -> Vec<usize> {
+    // Create a pool with a fixed number of worker threads whose jobs sleep on the clock
+    let pool = ThreadPool::with_clock(config.threads, clock);
+
+    // Hold the clock still until every job has been queued
+    let setup = clock::participate(clock);
+    let handles: Vec<_> = (0..config.iterations)
+        .map(|job| {
+            let delay = config.delay;
+            let job_clock = Arc::clone(clock);
+            pool.submit(move || {
+                println!("job {} runs on {}", job, thread::current().name().unwrap());
+                job_clock.sleep(delay);
+                job
+            })
+        })
+        .collect();
+    drop(setup);
+
+    // Wait for every job and collect its result
+    let results: Vec<usize> = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect();
+
+    // Stop the workers once the queue is empty
+    pool.shutdown();
+    println!("Application finished!");
+    results
+}
+
+/// Variant of `calculate_counter` that stops at the next step once cancelled
+pub fn calculate_counter_cancellable(
+    iterations: usize,
+    delay: Duration,
+    token: &CancellationToken,
+) -> i32 {
+    let mut counter = 0;
+    for i in 0..iterations {
+        // Stop between steps if cancellation was requested
+        if token.check().is_err() {
+            break;
+        }
+        println!("counter: {}", i);
+        // The token wakes the thread up as soon as it is cancelled
+        if token.sleep(delay).is_err() {
+            break;
+        }
+        counter += 1;
+    }
+    counter
+}
+
+/// Gives counting threads half the time they need, then cancels them.
+///
+/// The workers sleep on their cancellation token rather than on a clock, so
+/// that cancelling wakes them immediately.
+pub fn cancellable(config: &DemoConfig) -> Vec<i32> {
+    // Spawn the workers; each one receives its own cancellation token
+    let handles: Vec<_> = (0..config.threads)
+        .map(|_| {
+            let iterations = config.iterations;
+            let delay = config.delay;
+            cancel::spawn(move |token| calculate_counter_cancellable(iterations, delay, &token))
+        })
+        .collect();
+
+    // Every worker shares the same deadline: half of the time a full count takes
+    let full_count = config
+        .delay_times(config.iterations)
+        .expect("delay * iterations overflows a Duration");
+    let deadline = Instant::now().checked_add(full_count / 2);
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        let timeout = deadline.map_or(Duration::MAX, |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        });
+        let result = match handle.join_timeout(timeout) {
+            Ok(counter) => Ok(counter),
+            // Too slow: cancel it and wait for it to stop at its next step
+            Err(JoinTimeoutError::Timeout(handle)) => {
+                println!("cancelling a worker that ran out of time");
+                handle.cancel_and_join()
+            }
+            Err(JoinTimeoutError::Panicked(error)) => Err(error),
+        };
+        match result {
+            Ok(counter) => {
+                println!(
+                    "the worker counted {} of {} steps",
+                    counter, config.iterations
+                );
+                results.push(counter);
+            }
+            Err(error) => println!("the result is {}", error),
+        }
+    }
+    results
+}
+
+/// Moves ownership of a captured `String` into a spawned thread
+pub fn closure_using_move(config: &DemoConfig, clock: &SharedClock) {
+    // Create a String variable
+    let name = String::from("Gege");
+    let delay = config.delay;
+    let thread_clock = Arc::clone(clock);
+
+    // Define a closure that moves ownership of `name`
+    let closure = move || {
+        println!("Hello, {}!", name);
+        thread_clock.sleep(delay);
+    };
+
+    // Spawn a new thread and execute the closure
+    let handle = clock::spawn(clock, closure);
+    // Wait for thread to complete and capture the results
+    handle.join().unwrap();
+
+    // This line would cause a compilation error because `name` has been moved into the closure
+    // println!("Hello, {}!", name);
+    // Error: `name` is no longer accessible here due to ownership transfer
+}
+
+/// Passes a single message between two threads
+pub fn channel(config: &DemoConfig, clock: &SharedClock) -> String {
+    // Create a channel for sending and receiving messages
+    let (sender, receiver) = mpsc::channel();
+    let delay = config.delay;
+    let sender_clock = Arc::clone(clock);
+
+    // Spawn a thread to send a message after a delay
+    let handle1 = clock::spawn(clock, move || {
+        sender_clock.sleep(delay);
+        // Send a message to the receiver
+        sender.send("Hello, World!".to_string()).unwrap();
+    });
+
+    // Spawn a thread to receive the message; it only blocks on the channel,
+    // so it does not take part in the clock
+    let handle2 = thread::spawn(move || {
+        // Wait for and receive the message
+        let message = receiver.recv().unwrap();
+        // Print the received message
+        println!("The message is {}", message);
+        message
+    });
+
+    // Wait for both threads to complete
+    handle1.join().unwrap();
+    handle2.join().unwrap()
+}
+
+/// Hands one result back from a worker without a queue or a join for the value
+pub fn channel_oneshot(config: &DemoConfig, clock: &SharedClock) -> String {
+    // Both halves are used up by the single send and receive
+    let (sender, receiver) = oneshot::channel();
+    let delay = config.delay;
+    let sender_clock = Arc::clone(clock);
+
+    // The worker is detached; the channel alone tells us when it is done
+    clock::spawn(clock, move || {
+        sender_clock.sleep(delay);
+        sender.send("Hello, World!".to_string()).unwrap();
+    });
+
+    // Block until the value arrives; a worker that died without sending
+    // would show up as an error here instead of a hang
+    let message = receiver.recv().unwrap();
+    println!("The message is {}", message);
+    message
+}
+
+/// Queues several messages and stops the consumer by closing the channel
+pub fn channel_queue(config: &DemoConfig, clock: &SharedClock) -> Vec<String> {
+    // Create a channel that carries control messages next to the data
+    let (sender, receiver) = control::channel();
+    let iterations = config.iterations;
+    let delay = config.delay;
+    let sender_clock = Arc::clone(clock);
+
+    // Spawn a thread that will send messages to the receiver
+    let handle1 = clock::spawn(clock, move || {
+        // Loop to send the same message repeatedly
+        for _ in 0..iterations {
+            // Pause between messages
+            sender_clock.sleep(delay);
+            // Send the "Hello, World!" message via the channel, converting it to a String
+            sender.send("Hello, World!".to_string()).unwrap();
+        }
+        // After sending messages, close the channel instead of sending a magic "done" string
+        sender.close();
+    });
+
+    // Spawn a thread that will continuously receive messages from the channel
+    let handle2 = thread::spawn(move || {
+        let mut received = Vec::new();
+        loop {
+            // Block until a message is received or the channel ends
+            match receiver.recv() {
+                // Print the received payload
+                Ok(Message::Data(message)) => {
+                    println!("The message is {}", message);
+                    received.push(message);
+                }
+                // Nothing is buffered here, so flushes and reported errors are only logged
+                Ok(control) => println!("Control message {:?}", control),
+                // Every message sent before the close has been received, stop processing
+                Err(reason) => {
+                    println!("The channel ended: {}", reason);
+                    break;
+                }
+            }
+        }
+        received
+    });
+
+    // Wait for both threads to finish their execution
+    handle1.join().unwrap();
+    handle2.join().unwrap()
+}
+
+/// Consumes a channel through the receiver's iterator implementation
+pub fn channel_iterator(config: &DemoConfig, clock: &SharedClock) -> Vec<String> {
+    // Create a new channel for sending and receiving messages
+    let (sender, receiver) = mpsc::channel();
+    let iterations = config.iterations;
+    let delay = config.delay;
+    let sender_clock = Arc::clone(clock);
+
+    // Spawn a new thread that will send messages through the channel
+    let handle1 = clock::spawn(clock, move || {
+        for _ in 0..iterations {
+            // Pause execution to simulate delay between messages
+            sender_clock.sleep(delay);
+            // Send the "Hello, World!" message (converted to String) via the channel and unwrap to handle errors
+            sender.send("Hello, World!".to_string()).unwrap();
+        }
+    });
+
+    // Spawn another thread that will receive messages using the receiver's iterator implementation
+    let handle2 = thread::spawn(move || {
+        let mut received = Vec::new();
+        // Iterate over each incoming message from the receiver until the channel is closed
+        for value in receiver {
+            // Print the received message
+            println!("The message is {}", value);
+            received.push(value);
+        }
+        received
+    });
+
+    // Wait for the sending thread to finish execution
+    handle1.join().unwrap();
+    // Wait for the receiving thread to finish execution
+    handle2.join().unwrap()
+}
+
+/// Feeds one receiver from several cloned senders
+///
+/// Sender `n` (counting from 1) pauses `n * delay` between its messages, so the
+/// first sender is the fastest one. Every clone of the sender is a producer with
+/// its own id, and the receiver checks that each producer's messages arrive in
+/// the order they were sent.
+pub fn channel_multi_sender(
+    config: &DemoConfig,
+    clock: &SharedClock,
+) -> (Vec<Tagged<String>>, OrderReport) {
+    // Create a channel that tags every message with its producer and sequence number
+    let (sender, receiver) = tagged::channel();
+
+    // Hold the clock still until every sender has been spawned
+    let setup = clock::participate(clock);
+
+    // Spawn one thread per sender, each owning its own clone of the sender
+    let senders: Vec<_> = (1..=config.threads)
+        .map(|number| {
+            // Each clone gets the next producer id, so sender `number` is producer `number`
+            let mut sender = sender.clone();
+            let iterations = config.iterations;
+            let delay = config
+                .delay_times(number)
+                .expect("delay * threads overflows a Duration");
+            let sender_clock = Arc::clone(clock);
+            clock::spawn(clock, move || {
+                for _ in 0..iterations {
+                    // Pause execution to simulate a different processing delay per sender
+                    sender_clock.sleep(delay);
+                    // The tag says who sent the message, the payload does not have to
+                    sender.send("Hello, World!".to_string()).unwrap();
+                }
+            })
+        })
+        .collect();
+    drop(setup);
+    // Drop the original sender so the receiver stops once every clone is gone
+    drop(sender);
+
+    // Spawn a thread that will receive messages from the channel
+    let receiver_handle = thread::spawn(move || {
+        let mut received = Vec::new();
+        let mut checker = OrderChecker::new();
+        // Iterate over each message received from the channel until it is closed
+        for message in receiver {
+            println!(
+                "The message is {} from sender {} (#{})",
+                message.value, message.producer, message.sequence
+            );
+            checker.check(&message);
+            received.push(message);
+        }
+        let report = checker.report();
+        println!("{}", report);
+        (received, report)
+    });
+
+    // Wait for the sender and receiver threads to finish execution
+    for handle in senders {
+        handle.join().unwrap();
+    }
+    receiver_handle.join().unwrap()
+}
+
+/// Gives every sender its own channel and waits on all receivers at once
+///
+/// Same timing as [`channel_multi_sender`], but the consumer knows which
+/// sender each message came from and stops watching a channel once it is
+/// disconnected.
+pub fn channel_select(config: &DemoConfig, clock: &SharedClock) -> Vec<String> {
+    // Hold the clock still until every sender has been spawned
+    let setup = clock::participate(clock);
+
+    // Spawn one thread per sender, each with a channel of its own
+    let (senders, mut receivers): (Vec<_>, Vec<_>) = (1..=config.threads)
+        .map(|number| {
+            let (sender, receiver) = mpsc::channel();
+            let iterations = config.iterations;
+            let delay = config
+                .delay_times(number)
+                .expect("delay * threads overflows a Duration");
+            let sender_clock = Arc::clone(clock);
+            let handle = clock::spawn(clock, move || {
+                for _ in 0..iterations {
+                    sender_clock.sleep(delay);
+                    sender
+                        .send(format!("Hello, World! from sender {}", number))
+                        .unwrap();
+                }
+            });
+            (handle, (number, receiver))
+        })
+        .unzip();
+    drop(setup);
+
+    // The consumer polls instead of sleeping on the clock, so it is a plain thread
+    let receiver_handle = thread::spawn(move || {
+        let mut received = Vec::new();
+        while !receivers.is_empty() {
+            // Build a select with one arm per channel that is still connected
+            let mut select = Select::new();
+            for (position, (_, receiver)) in receivers.iter().enumerate() {
+                select = select.recv(receiver, move |message| (position, message));
+            }
+            match select.wait().value {
+                (position, Ok(value)) => {
+                    println!("Sender {} says {}", receivers[position].0, value);
+                    received.push(value);
+                }
+                (position, Err(_)) => {
+                    // Every message of this sender has been received
+                    receivers.remove(position);
+                }
+            }
+        }
+        received
+    });
+
+    for handle in senders {
+        handle.join().unwrap();
+    }
+    receiver_handle.join().unwrap()
+}
+
+/// Broadcasts every message to `threads` subscribers, which all receive all of them
+pub fn channel_broadcast(config: &DemoConfig, clock: &SharedClock) -> Vec<Vec<String>> {
+    // Buffer every message so no subscriber can lag behind
+    let (sender, receiver) = broadcast::channel(config.iterations.max(1));
+    let iterations = config.iterations;
+    let delay = config.delay;
+    let sender_clock = Arc::clone(clock);
+
+    // Subscribe before sending anything; late subscribers only see newer messages
+    let subscribers: Vec<_> = (1..=config.threads)
+        .map(|number| {
+            let receiver = receiver.clone();
+            thread::spawn(move || {
+                let mut received = Vec::new();
+                for value in receiver {
+                    println!("Subscriber {} got {}", number, value);
+                    received.push(value);
+                }
+                received
+            })
+        })
+        .collect();
+    drop(receiver);
+
+    // The producer closes the channel by dropping its sender
+    let producer = clock::spawn(clock, move || {
+        for _ in 0..iterations {
+            sender_clock.sleep(delay);
+            sender.send("Hello, World!".to_string()).unwrap();
+        }
+    });
+
+    producer.join().unwrap();
+    subscribers
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect()
+}
+
+/// Fans `iterations` jobs out to `threads` consumers sharing one MPMC receiver
+///
+/// Returns the jobs each consumer handled.
+pub fn channel_work_queue(config: &DemoConfig, clock: &SharedClock) -> Vec<Vec<usize>> {
+    let (sender, receiver) = mpmc::unbounded();
+
+    // Queue every job up front and hang up, so consumers never wait for more work
+    for job in 0..config.iterations {
+        sender.send(job).unwrap();
+    }
+    drop(sender);
+
+    // Hold the clock still until every consumer has been spawned
+    let setup = clock::participate(clock);
+    let consumers: Vec<_> = (1..=config.threads)
+        .map(|number| {
+            // Every consumer gets its own clone of the receiver
+            let receiver = receiver.clone();
+            let delay = config.delay;
+            let consumer_clock = Arc::clone(clock);
+            clock::spawn(clock, move || {
+                let mut handled = Vec::new();
+                for job in receiver {
+                    println!("Consumer {} handles job {}", number, job);
+                    consumer_clock.sleep(delay);
+                    handled.push(job);
+                }
+                handled
+            })
+        })
+        .collect();
+    drop(setup);
+    drop(receiver);
+
+    consumers
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect()
+}
+
+/// Publishes `iterations` versions of a setting to `threads` watchers
+///
+/// Watchers may skip versions when they fall behind, but each of them ends up
+/// with the last published value, which is returned per watcher.
+pub fn channel_watch(config: &DemoConfig, clock: &SharedClock) -> Vec<usize> {
+    let (sender, receiver) = watch::channel(0);
+
+    // Watchers block on the channel, so they do not take part in the clock
+    let watchers: Vec<_> = (1..=config.threads)
+        .map(|number| {
+            let mut receiver = receiver.clone();
+            thread::spawn(move || {
+                while let Ok(change) = receiver.changed() {
+                    println!(
+                        "Watcher {} sees version {} (missed {}): {}",
+                        number,
+                        change.version,
+                        change.missed,
+                        *receiver.borrow()
+                    );
+                }
+                *receiver.borrow()
+            })
+        })
+        .collect();
+    drop(receiver);
+
+    // Publish a new value after every pause; dropping the sender ends the watch
+    let iterations = config.iterations;
+    let delay = config.delay;
+    let publisher_clock = Arc::clone(clock);
+    clock::spawn(clock, move || {
+        for value in 1..=iterations {
+            publisher_clock.sleep(delay);
+            sender.send_replace(value);
+        }
+    })
+    .join()
+    .unwrap();
+
+    watchers
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect()
+}
+
+// Declare a mutable static variable COUNTER with an initial value of 0
+static mut COUNTER: i32 = 0;
+
+/// Demonstrates a race condition on an unsynchronized `static mut` counter
+pub fn race_condition(config: &DemoConfig) -> i32 {
+    // Reset the counter so repeated runs start from zero
+    unsafe {
+        COUNTER = 0;
+    }
+
+    // Create a vector to store thread handles
+    let mut handles = vec![];
+
+    for _ in 0..config.threads {
+        let iterations = config.iterations;
+        let handle = thread::spawn(move || unsafe {
+            // Each thread increments the COUNTER variable `iterations` times
+            for _ in 0..iterations {
+                COUNTER += 1;
+            }
+        });
+        // Store the thread handle in the vector
+        handles.push(handle);
+    }
+
+    // Wait for all spawned threads to complete
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // Print the final value of COUNTER
+    let counter = unsafe { COUNTER };
+    println!("Counter: {}", counter);
+
+    // **Race Condition Explanation:**
+    // Since multiple threads modify the same shared variable (COUNTER) without synchronization,
+    // it can lead to data races. The final value of COUNTER might not be as expected
+    // because of concurrent read/write operations leading to lost updates.
+    counter
+}
+
+/// Uses atomic operations on a static counter to avoid race conditions
+pub fn atomic(config: &DemoConfig) -> i32 {
+    // Import AtomicI32 and memory ordering enum from the standard library
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    // Declare an atomic counter with an initial value of 0.
+    // Using an atomic variable ensures that concurrent modifications are safe.
+    static COUNTER_NEW: AtomicI32 = AtomicI32::new(0);
+    // Reset the counter so repeated runs start from zero
+    COUNTER_NEW.store(0, Ordering::Relaxed);
+
+    // Create a vector to store thread handles
+    let mut handles = vec![];
+
+    // Spawn threads to increment the atomic counter concurrently
+    for _ in 0..config.threads {
+        let iterations = config.iterations;
+        let handle = thread::spawn(move || {
+            for _ in 0..iterations {
+                // Atomically add 1 to COUNTER_NEW using relaxed memory ordering.
+                // The relaxed ordering allows the counter to be incremented without additional synchronization,
+                // which is acceptable in this simple counting scenario.
+                COUNTER_NEW.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        // Store the thread handle in the vector
+        handles.push(handle);
+    }
+
+    // Wait for all spawned threads to complete their execution
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // Load and print the final value of the atomic counter using relaxed memory ordering.
+    // The use of atomics here prevents data races, ensuring the correct final count.
+    let counter = COUNTER_NEW.load(Ordering::Relaxed);
+    println!("Counter: {}", counter);
+
+    // **Explanation:**
+    // This code uses `AtomicI32` to safely share and update a counter across multiple threads.
+    // Atomic types in Rust, such as `AtomicI32`, provide a way to perform lock-free concurrent modifications.
+    // The operations such as `fetch_add` and `load` are guaranteed to be atomic,
+    // meaning that they are performed as a single, indivisible operation.
+    //
+    // For more details, refer to the official Rust documentation on atomics:
+    // https://doc.rust-lang.org/std/sync/atomic/index.html
+    counter
+}
+
+/// Uses `Arc<AtomicI32>` to prevent race conditions in a multithreaded context
+pub fn atomic_arc(config: &DemoConfig) -> i32 {
+    // Import necessary atomic types and memory ordering from the Rust standard library
+    use std::sync::{
+        Arc,
+        atomic::{AtomicI32, Ordering},
+    };
+
+    // Create an `Arc<AtomicI32>` instance to allow multiple threads to share and update a single atomic counter
+    let counter_new: Arc<AtomicI32> = Arc::new(AtomicI32::new(0));
+
+    // Create a vector to store thread handles
+    let mut handles = vec![];
+
+    // Spawn threads, each of which will increment the shared atomic counter
+    for _ in 0..config.threads {
+        // Clone the `Arc` pointer to share ownership of `counter_new` across threads
+        let counter_new_clone = Arc::clone(&counter_new);
+        let iterations = config.iterations;
+        let handle = thread::spawn(move || {
+            for _ in 0..iterations {
+                // Perform an atomic addition to ensure thread-safe updates
+                counter_new_clone.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        // Store the thread handle in the vector
+        handles.push(handle);
+    }
+
+    // Wait for all threads to complete their execution
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // Load and print the final value of the atomic counter
+    let counter = counter_new.load(Ordering::Relaxed);
+    println!("Counter: {}", counter);
+
+    // **Explanation:**
+    // This implementation utilizes `Arc<AtomicI32>` to safely share and modify a counter across multiple threads.
+    // `Arc` (Atomic Reference Counting) allows multiple threads to hold references to the same counter,
+    // while `AtomicI32` ensures that increments are performed atomically, avoiding data races.
+    //
+    // Why use `Arc`?
+    // - Unlike `static mut`, which can lead to race conditions, `Arc` ensures safe shared ownership across threads.
+    // - Since `Arc` itself is thread-safe, it allows multiple threads to modify the counter without needing explicit locks.
+    //
+    // Why use `AtomicI32`?
+    // - Atomic operations like `fetch_add` are lock-free, meaning they avoid the performance overhead of mutex locks.
+    // - This ensures high-performance concurrent updates to the shared counter.
+    //
+    // Reference:
+    // - `Arc`: https://doc.rust-lang.org/std/sync/struct.Arc.html
+    // - `AtomicI32`: https://doc.rust-lang.org/std/sync/atomic/struct.AtomicI32.html
+    counter
+}
+
+/// Uses `Arc<Mutex<i32>>` to avoid race conditions
+pub fn mutex(config: &DemoConfig) -> i32 {
+    // Import necessary synchronization primitives from the standard library
+    use std::sync::{Arc, Mutex};
+
+    // Create an atomic reference-counted (Arc) Mutex wrapping an i32 value.
+    // `Arc` allows multiple threads to share ownership of the mutex.
+    // `Mutex` ensures that only one thread can access the counter at a time.
+    let counter_new: Arc<Mutex<i32>> = Arc::new(Mutex::new(0));
+
+    // Create a vector to hold the thread handles
+    let mut handles = vec![];
+
+    for _ in 0..config.threads {
+        // Clone the Arc to share ownership of the mutex across threads
+        let counter_new_clone = Arc::clone(&counter_new);
+        let iterations = config.iterations;
+        let handle = thread::spawn(move || {
+            for _ in 0..iterations {
+                // Lock the mutex to get mutable access to the data
+                // If the lock is poisoned, unwrap will panic
+                let mut data = counter_new_clone.lock().unwrap();
+                // Increment the counter by 1
+                *data += 1;
+                // Mutex is automatically unlocked when `data` goes out of scope
+            }
+        });
+        // Save the thread handle for later joining
+        handles.push(handle);
+    }
+
+    // Wait for all threads to complete
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // Lock the mutex one last time to read and print the final counter value
+    let counter = *counter_new.lock().unwrap();
+    println!("Counter: {}", counter);
+
+    // **Explanation:**
+    // This code uses `Arc<Mutex<i32>>` to allow multiple threads to safely update a shared counter.
+    // The `Mutex` ensures that only one thread at a time can modify the counter, thus preventing race conditions.
+    // `Arc` (Atomic Reference Counting) is used to share the mutex safely across threads.
+    //
+    // For more details, refer to the official Rust documentation on Mutex:
+    // https://doc.rust-lang.org/std/sync/struct.Mutex.html
+    counter
+}
+
+/// This module is conditionally compiled only when running tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+
+    /// Parameters a demo was originally written with
+    fn defaults(name: &str) -> DemoConfig {
+        find(name).unwrap().defaults.clone()
+    }
+
+    /// Marks this function as a test case
+    #[test]
+    fn threaded_test() {
+        let clock = VirtualClock::shared();
+        threaded(&defaults("threaded"), &clock);
+        // The main thread sleeps for the whole 6 steps of 1 second
+        assert_eq!(clock.now(), Duration::from_secs(6));
+    }
+
+    #[test]
+    fn join_threads_test() {
+        let clock = VirtualClock::shared();
+        assert_eq!(join_threads(&defaults("join-threads"), &clock), Some(6));
+        assert_eq!(clock.now(), Duration::from_secs(6));
+    }
+
+    /// Test function for sequential processing
+    #[test]
+    fn sequential_process_test() {
+        let clock = VirtualClock::shared();
+        assert_eq!(
+            sequential_process(&defaults("sequential"), &clock),
+            vec![6, 6]
+        );
+        // Two runs of 6 seconds back to back
+        assert_eq!(clock.now(), Duration::from_secs(12));
+    }
+
+    /// Test function for parallel processing
+    #[test]
+    fn parallel_process_test() {
+        let clock = VirtualClock::shared();
+        assert_eq!(parallel_process(&defaults("parallel"), &clock), vec![6, 6]);
+        // Two runs of 6 seconds side by side
+        assert_eq!(clock.now(), Duration::from_secs(6));
+    }
+
+    #[test]
+    fn thread_pool_test() {
+        let clock = VirtualClock::shared();
+        assert_eq!(
+            thread_pool(&defaults("thread-pool"), &clock),
+            vec![0, 1, 2, 3, 4, 5]
+        );
+        // Six jobs of 1 second on two workers
+        assert_eq!(clock.now(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn cancellable_test() {
+        let started = Instant::now();
+        // This demo sleeps on its tokens, so keep the real delays short
+        let results = cancellable(&DemoConfig::new(2, 10, Duration::from_millis(20)));
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|&counter| counter < 10), "{:?}", results);
+        assert!(started.elapsed() < Duration::from_millis(200));
+    }
+
+    /// Test function for closure using `move`
+    #[test]
+    fn closure_using_move_test() {
+        let clock = VirtualClock::shared();
+        closure_using_move(&defaults("closure-move"), &clock);
+        assert_eq!(clock.now(), Duration::from_secs(2));
+    }
+
+    /// Test function for message passing between threads
+    #[test]
+    fn channel_test() {
+        let clock = VirtualClock::shared();
+        assert_eq!(channel(&defaults("channel"), &clock), "Hello, World!");
+        assert_eq!(clock.now(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn channel_oneshot_test() {
+        let clock = VirtualClock::shared();
+        assert_eq!(
+            channel_oneshot(&defaults("channel-oneshot"), &clock),
+            "Hello, World!"
+        );
+        assert_eq!(clock.now(), Duration::from_secs(2));
+    }
+
+    /// Test function for message queuing using a channel
+    #[test]
+    fn channel_queue_test() {
+        let clock = VirtualClock::shared();
+        assert_eq!(channel_queue(&defaults("channel-queue"), &clock).len(), 5);
+        assert_eq!(clock.now(), Duration::from_secs(10));
+    }
+
+    /// Test function for iterating over messages from a channel using its iterator implementation
+    #[test]
+    fn channel_iterator_test() {
+        let clock = VirtualClock::shared();
+        assert_eq!(
+            channel_iterator(&defaults("channel-iterator"), &clock).len(),
+            5
+        );
+        assert_eq!(clock.now(), Duration::from_secs(10));
+    }
+
+    /// Test function for demonstrating a multi-sender channel using mpsc::channel in Rust
+    #[test]
+    fn channel_multi_sender_test() {
+        let clock = VirtualClock::shared();
+        let (received, report) = channel_multi_sender(&defaults("channel-multi-sender"), &clock);
+        assert_eq!(received.len(), 10);
+        // The faster sender delivers its first message first
+        assert_eq!(received[0].producer, 1);
+        assert_eq!(received[0].value, "Hello, World!");
+        assert!(report.is_ordered());
+        assert!(report.producers.values().all(|stats| stats.received == 5));
+        // The slower sender needs 5 pauses of 2 seconds
+        assert_eq!(clock.now(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn channel_select_test() {
+        let clock = VirtualClock::shared();
+        let received = channel_select(&defaults("channel-select"), &clock);
+        assert_eq!(received.len(), 10);
+        // Virtual time can run ahead of the polling consumer, so only the
+        // number of messages per sender is fixed, not their interleaving
+        for number in 1..=2 {
+            let expected = format!("Hello, World! from sender {}", number);
+            assert_eq!(received.iter().filter(|m| **m == expected).count(), 5);
+        }
+        assert_eq!(clock.now(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn channel_broadcast_test() {
+        let clock = VirtualClock::shared();
+        let received = channel_broadcast(&defaults("channel-broadcast"), &clock);
+        assert_eq!(received.len(), 3);
+        assert!(received.iter().all(|messages| messages.len() == 5));
+        assert_eq!(clock.now(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn channel_work_queue_test() {
+        let clock = VirtualClock::shared();
+        let handled = channel_work_queue(&defaults("channel-work-queue"), &clock);
+        assert_eq!(handled.len(), 2);
+        let mut jobs: Vec<usize> = handled.into_iter().flatten().collect();
+        jobs.sort();
+        assert_eq!(jobs, vec![0, 1, 2, 3, 4, 5]);
+        // Six jobs of 1 second on two consumers
+        assert_eq!(clock.now(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn channel_watch_test() {
+        let clock = VirtualClock::shared();
+        assert_eq!(
+            channel_watch(&defaults("channel-watch"), &clock),
+            vec![5, 5]
+        );
+        assert_eq!(clock.now(), Duration::from_secs(5));
+    }
+
+    /// Test function for demonstrating a race condition
+    #[test]
+    fn race_condition_test() {
+        // Lost updates may make the result smaller, never larger
+        assert!(race_condition(&defaults("race-condition")) <= 10_000_000);
+    }
+
+    /// Test function demonstrating the use of atomic operations to avoid race conditions
+    #[test]
+    fn atomic_test() {
+        assert_eq!(atomic(&defaults("atomic")), 10_000_000);
+    }
+
+    /// Test function demonstrating the use of `Arc<AtomicI32>` to prevent race conditions in a multithreaded context.
+    #[test]
+    fn atomic_arc_test() {
+        assert_eq!(atomic_arc(&defaults("atomic-arc")), 10_000_000);
+    }
+
+    /// Test function demonstrating the use of `Arc<Mutex<i32>>` to avoid race conditions
+    #[test]
+    fn mutex_test() {
+        assert_eq!(mutex(&defaults("mutex")), 10_000_000);
+    }
+
+    #[test]
+    fn demo_names_are_unique() {
+        for (index, demo) in DEMOS.iter().enumerate() {
+            assert!(
+                DEMOS[index + 1..]
+                    .iter()
+                    .all(|other| other.name != demo.name)
+            );
+        }
+    }
+}
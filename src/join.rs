@@ -0,0 +1,353 @@
+//! Thread handles whose `join` reports panics as a typed [`JoinError`].
+//!
+//! `std::thread::JoinHandle::join` hands back the raw `Box<dyn Any + Send>`
+//! passed to `panic!`, which prints as `Any { .. }`. The handles here extract
+//! the panic message from `&str`/`String` payloads and remember the name of
+//! the thread and the source location of the panic.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, Once};
+use std::thread::{self, Thread};
+
+/// Source location a panic was raised at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for PanicLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+thread_local! {
+    /// Location of the latest panic raised on this thread
+    static LAST_PANIC: RefCell<Option<PanicLocation>> = const { RefCell::new(None) };
+}
+
+/// Installs (once per process) a panic hook recording where each thread panicked.
+///
+/// The previously installed hook still runs, so panic messages are printed as usual.
+pub fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Some(location) = info.location() {
+                let location = PanicLocation {
+                    file: location.file().to_string(),
+                    line: location.line(),
+                    column: location.column(),
+                };
+                LAST_PANIC.with(|last| *last.borrow_mut() = Some(location));
+            }
+            previous(info);
+        }));
+    });
+}
+
+/// Takes the location recorded for the latest panic on the calling thread
+pub fn take_panic_location() -> Option<PanicLocation> {
+    LAST_PANIC.with(|last| last.borrow_mut().take())
+}
+
+/// Extracts the message of a panic payload created by `panic!`
+pub fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        Some(message)
+    } else {
+        payload.downcast_ref::<String>().map(String::as_str)
+    }
+}
+
+/// A thread that panicked instead of returning a value
+pub struct JoinError {
+    thread_name: Option<String>,
+    location: Option<PanicLocation>,
+    payload: Box<dyn Any + Send + 'static>,
+}
+
+impl JoinError {
+    /// Wraps a panic payload caught on a thread called `thread_name`
+    pub fn new(
+        payload: Box<dyn Any + Send + 'static>,
+        thread_name: Option<String>,
+        location: Option<PanicLocation>,
+    ) -> JoinError {
+        JoinError {
+            thread_name,
+            location,
+            payload,
+        }
+    }
+
+    /// Name of the thread that panicked, if it had one
+    pub fn thread_name(&self) -> Option<&str> {
+        self.thread_name.as_deref()
+    }
+
+    /// Message passed to `panic!`, unless the payload was not a string
+    pub fn message(&self) -> Option<&str> {
+        panic_message(self.payload.as_ref())
+    }
+
+    /// Where the panic was raised, if the panic hook could record it
+    pub fn location(&self) -> Option<&PanicLocation> {
+        self.location.as_ref()
+    }
+
+    /// Gives back the original payload, e.g. to `resume_unwind` it
+    pub fn into_payload(self) -> Box<dyn Any + Send + 'static> {
+        self.payload
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinError")
+            .field("thread_name", &self.thread_name)
+            .field("message", &self.message())
+            .field("location", &self.location)
+            .finish()
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "thread '{}' panicked",
+            self.thread_name().unwrap_or("<unnamed>")
+        )?;
+        if let Some(location) = &self.location {
+            write!(f, " at {}", location)?;
+        }
+        match self.message() {
+            Some(message) => write!(f, ": {}", message),
+            None => write!(f, ": <non-string panic payload>"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// Runs `f`, turning a panic into a [`JoinError`] attributed to the current thread
+pub fn catch_panic<F, T>(f: F) -> Result<T, JoinError>
+where
+    F: FnOnce() -> T,
+{
+    install_panic_hook();
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        JoinError::new(
+            payload,
+            thread::current().name().map(String::from),
+            take_panic_location(),
+        )
+    })
+}
+
+/// Owned permission to join a thread spawned by [`spawn`] or [`spawn_named`]
+pub struct JoinHandle<T> {
+    inner: thread::JoinHandle<T>,
+    /// Filled in by the thread itself when it panics
+    location: Arc<Mutex<Option<PanicLocation>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Waits for the thread to finish and returns its value or a typed panic
+    pub fn join(self) -> Result<T, JoinError> {
+        let thread_name = self.inner.thread().name().map(String::from);
+        self.inner.join().map_err(|payload| {
+            let location = self.location.lock().unwrap().take();
+            JoinError::new(payload, thread_name, location)
+        })
+    }
+
+    /// The handle of the underlying thread
+    pub fn thread(&self) -> &Thread {
+        self.inner.thread()
+    }
+
+    /// Whether the thread has finished running
+    pub fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("thread", self.inner.thread())
+            .finish()
+    }
+}
+
+/// Spawns an unnamed thread, like `std::thread::spawn`
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_with(thread::Builder::new(), f).expect("failed to spawn thread")
+}
+
+/// Spawns a thread with a name that shows up in its [`JoinError`]
+pub fn spawn_named<F, T>(name: impl Into<String>, f: F) -> io::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_with(thread::Builder::new().name(name.into()), f)
+}
+
+fn spawn_with<F, T>(builder: thread::Builder, f: F) -> io::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    install_panic_hook();
+    let location = Arc::new(Mutex::new(None));
+    let thread_location = Arc::clone(&location);
+    let inner = builder.spawn(move || match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(payload) => {
+            // Hand the location over before unwinding out of the thread
+            *thread_location.lock().unwrap() = take_panic_location();
+            panic::resume_unwind(payload)
+        }
+    })?;
+    Ok(JoinHandle { inner, location })
+}
+
+/// Every thread that panicked while joining a group of handles
+#[derive(Debug)]
+pub struct JoinErrors {
+    /// Index of the handle in the joined group and its error
+    pub errors: Vec<(usize, JoinError)>,
+    /// Number of handles that were joined
+    pub joined: usize,
+}
+
+impl fmt::Display for JoinErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} threads panicked",
+            self.errors.len(),
+            self.joined
+        )?;
+        for (index, error) in &self.errors {
+            write!(f, "\n  #{}: {}", index, error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for JoinErrors {}
+
+/// Joins every handle, even after one of them failed.
+///
+/// Returns the values in order when no thread panicked, otherwise every error
+/// together with the position of its handle.
+pub fn join_all<T, I>(handles: I) -> Result<Vec<T>, JoinErrors>
+where
+    I: IntoIterator<Item = JoinHandle<T>>,
+{
+    let mut values = Vec::new();
+    let mut errors = Vec::new();
+    let mut joined = 0;
+    for (index, handle) in handles.into_iter().enumerate() {
+        joined += 1;
+        match handle.join() {
+            Ok(value) => values.push(value),
+            Err(error) => errors.push((index, error)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(values)
+    } else {
+        Err(JoinErrors { errors, joined })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_returns_the_value() {
+        assert_eq!(spawn(|| 6 * 7).join().unwrap(), 42);
+    }
+
+    #[test]
+    fn str_and_string_payloads_become_messages() {
+        let error = spawn_named("worker", || -> () { panic!("static message") })
+            .unwrap()
+            .join()
+            .unwrap_err();
+        assert_eq!(error.thread_name(), Some("worker"));
+        assert_eq!(error.message(), Some("static message"));
+
+        let code = 7;
+        let error = spawn(move || -> () { panic!("formatted {}", code) })
+            .join()
+            .unwrap_err();
+        assert_eq!(error.thread_name(), None);
+        assert_eq!(error.message(), Some("formatted 7"));
+    }
+
+    #[test]
+    fn location_points_at_the_panic() {
+        let line = line!() + 1;
+        let error = spawn(|| -> () { panic!("here") }).join().unwrap_err();
+        let location = error.location().unwrap();
+        assert!(location.file.ends_with("join.rs"), "{}", location);
+        assert_eq!(location.line, line);
+        assert!(
+            error
+                .to_string()
+                .starts_with("thread '<unnamed>' panicked at ")
+        );
+        assert!(error.to_string().ends_with(": here"));
+    }
+
+    #[test]
+    fn non_string_payloads_are_kept() {
+        let error = spawn(|| -> () { panic::panic_any(17_u8) })
+            .join()
+            .unwrap_err();
+        assert_eq!(error.message(), None);
+        assert_eq!(error.into_payload().downcast_ref::<u8>(), Some(&17));
+    }
+
+    #[test]
+    fn join_all_aggregates_every_failure() {
+        let handles: Vec<_> = (0..5)
+            .map(|i| {
+                spawn_named(format!("worker-{}", i), move || {
+                    if i % 2 == 1 {
+                        panic!("odd worker {}", i);
+                    }
+                    i
+                })
+                .unwrap()
+            })
+            .collect();
+        let errors = join_all(handles).unwrap_err();
+        assert_eq!(errors.joined, 5);
+        let failed: Vec<_> = errors.errors.iter().map(|(index, _)| *index).collect();
+        assert_eq!(failed, vec![1, 3]);
+        assert_eq!(errors.errors[1].1.thread_name(), Some("worker-3"));
+        assert!(errors.to_string().starts_with("2 of 5 threads panicked"));
+
+        let handles: Vec<_> = (0..3).map(|i| spawn(move || i)).collect();
+        assert_eq!(join_all(handles).unwrap(), vec![0, 1, 2]);
+    }
+}
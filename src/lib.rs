@@ -0,0 +1,21 @@
+//! Experiments exploring concurrency in Rust.
+//!
+//! Every experiment lives in [`demos`] and can be run from the command line
+//! through [`cli`], e.g. `cargo run -- run parallel --threads 4`. The
+//! [`bench`] harness measures how workloads scale across threads, and
+//! [`counter`] compares ways of sharing one counter between them, including
+//! the spinlocks of [`lock`].
+
+pub mod bench;
+pub mod cache_padded;
+pub mod cancel;
+pub mod channel;
+pub mod cli;
+pub mod clock;
+pub mod counter;
+mod deadline;
+pub mod demos;
+pub mod join;
+pub mod litmus;
+pub mod lock;
+pub mod pool;
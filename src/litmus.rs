@@ -0,0 +1,433 @@
+//! Memory ordering litmus tests.
+//!
+//! A litmus test is a tiny program of two to four threads doing loads and
+//! stores on shared atomics, together with the question of which values the
+//! loads may return. [`run`] executes a [`Pattern`] many times and counts how
+//! often each [`Outcome`] showed up, and [`Pattern::allows`] says which
+//! outcomes the Rust (C++20) memory model permits for the chosen ordering.
+//! Seeing an allowed outcome is up to the hardware and the timing; seeing a
+//! forbidden one is a bug in the compiler, the CPU or the runner.
+//!
+//! The orderings are applied to every access of a test: stores use the
+//! release half and loads the acquire half, so `Acquire` means acquire loads
+//! and relaxed stores, `Release` release stores and relaxed loads, `AcqRel`
+//! both, and `SeqCst` makes every access sequentially consistent.
+//!
+//! Instances are run in batches: every thread walks through the same batch of
+//! fresh instances on its own, with a barrier between batches only, so the
+//! threads race over a whole batch instead of meeting before every instance.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Barrier;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The orderings a pattern can be run with
+pub const ORDERINGS: [Ordering; 5] = [
+    Ordering::Relaxed,
+    Ordering::Acquire,
+    Ordering::Release,
+    Ordering::AcqRel,
+    Ordering::SeqCst,
+];
+
+/// A classic litmus test shape
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Pattern {
+    /// Message passing: `x = 1; y = 1` against `r0 = y; r1 = x`.
+    /// Forbidden: `r0 = 1, r1 = 0`, the flag without the data.
+    MessagePassing,
+    /// Store buffering: `x = 1; r0 = y` against `y = 1; r1 = x`.
+    /// Forbidden: `r0 = 0, r1 = 0`, each store missed by the other thread.
+    StoreBuffering,
+    /// Load buffering: `r0 = x; y = 1` against `r1 = y; x = 1`.
+    /// Forbidden: `r0 = 1, r1 = 1`, each load seeing a later store.
+    LoadBuffering,
+    /// Independent reads of independent writes: `x = 1`, `y = 1`,
+    /// `r0 = x; r1 = y` and `r2 = y; r3 = x` on four threads.
+    /// Forbidden: `1, 0, 1, 0`, the readers disagreeing on which write came first.
+    Iriw,
+}
+
+/// Every pattern, in the order they are reported
+pub const PATTERNS: [Pattern; 4] = [
+    Pattern::MessagePassing,
+    Pattern::StoreBuffering,
+    Pattern::LoadBuffering,
+    Pattern::Iriw,
+];
+
+impl Pattern {
+    /// Short name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pattern::MessagePassing => "mp",
+            Pattern::StoreBuffering => "sb",
+            Pattern::LoadBuffering => "lb",
+            Pattern::Iriw => "iriw",
+        }
+    }
+
+    /// Looks a pattern up by its short name
+    pub fn from_name(name: &str) -> Option<Pattern> {
+        PATTERNS.into_iter().find(|pattern| pattern.name() == name)
+    }
+
+    /// Number of threads the pattern runs on
+    pub fn threads(&self) -> usize {
+        match self {
+            Pattern::Iriw => 4,
+            _ => 2,
+        }
+    }
+
+    /// Number of registers, i.e. loads, in an outcome
+    pub fn registers(&self) -> usize {
+        match self {
+            Pattern::Iriw => 4,
+            _ => 2,
+        }
+    }
+
+    /// The outcome the memory model rules out under strong enough orderings
+    pub fn relaxed_outcome(&self) -> Outcome {
+        match self {
+            Pattern::MessagePassing => Outcome::new(&[1, 0]),
+            Pattern::StoreBuffering => Outcome::new(&[0, 0]),
+            Pattern::LoadBuffering => Outcome::new(&[1, 1]),
+            Pattern::Iriw => Outcome::new(&[1, 0, 1, 0]),
+        }
+    }
+
+    /// Whether the memory model permits `outcome` when every access uses `ordering`
+    pub fn allows(&self, ordering: Ordering, outcome: &Outcome) -> bool {
+        if *outcome != self.relaxed_outcome() {
+            return true;
+        }
+        let synchronizes = matches!(ordering, Ordering::AcqRel | Ordering::SeqCst);
+        match self {
+            // A release store read by an acquire load orders the data before the flag,
+            // and in LB makes each thread's load happen before its own store
+            Pattern::MessagePassing | Pattern::LoadBuffering => !synchronizes,
+            // Only a single total order over all accesses rules these out
+            Pattern::StoreBuffering | Pattern::Iriw => ordering != Ordering::SeqCst,
+        }
+    }
+
+    /// Runs the part of thread `thread` on one instance
+    fn step(&self, thread: usize, instance: &Instance, ordering: Ordering) {
+        let (x, y, registers) = (&instance.x, &instance.y, &instance.registers);
+        let store = store_ordering(ordering);
+        let load = load_ordering(ordering);
+        let read = |register: usize, location: &AtomicU32| {
+            registers[register].store(location.load(load), Ordering::Relaxed);
+        };
+        match (self, thread) {
+            (Pattern::MessagePassing, 0) => {
+                x.store(1, store);
+                y.store(1, store);
+            }
+            (Pattern::MessagePassing, _) => {
+                read(0, y);
+                read(1, x);
+            }
+            (Pattern::StoreBuffering, 0) => {
+                x.store(1, store);
+                read(0, y);
+            }
+            (Pattern::StoreBuffering, _) => {
+                y.store(1, store);
+                read(1, x);
+            }
+            (Pattern::LoadBuffering, 0) => {
+                read(0, x);
+                y.store(1, store);
+            }
+            (Pattern::LoadBuffering, _) => {
+                read(1, y);
+                x.store(1, store);
+            }
+            (Pattern::Iriw, 0) => x.store(1, store),
+            (Pattern::Iriw, 1) => y.store(1, store),
+            (Pattern::Iriw, 2) => {
+                read(0, x);
+                read(1, y);
+            }
+            (Pattern::Iriw, _) => {
+                read(2, y);
+                read(3, x);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The ordering stores use when a test runs with `ordering`
+fn store_ordering(ordering: Ordering) -> Ordering {
+    match ordering {
+        Ordering::Release | Ordering::AcqRel => Ordering::Release,
+        Ordering::SeqCst => Ordering::SeqCst,
+        _ => Ordering::Relaxed,
+    }
+}
+
+/// The ordering loads use when a test runs with `ordering`
+fn load_ordering(ordering: Ordering) -> Ordering {
+    match ordering {
+        Ordering::Acquire | Ordering::AcqRel => Ordering::Acquire,
+        Ordering::SeqCst => Ordering::SeqCst,
+        _ => Ordering::Relaxed,
+    }
+}
+
+/// Parses an ordering name as written on the command line, e.g. `acqrel`
+pub fn parse_ordering(name: &str) -> Option<Ordering> {
+    ORDERINGS
+        .into_iter()
+        .find(|ordering| ordering_name(*ordering) == name)
+}
+
+/// Lower case name of an ordering
+pub fn ordering_name(ordering: Ordering) -> &'static str {
+    match ordering {
+        Ordering::Relaxed => "relaxed",
+        Ordering::Acquire => "acquire",
+        Ordering::Release => "release",
+        Ordering::AcqRel => "acqrel",
+        _ => "seqcst",
+    }
+}
+
+/// The values the loads of one run returned, register by register
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Outcome {
+    registers: [u32; 4],
+    len: usize,
+}
+
+impl Outcome {
+    /// Creates an outcome from up to four register values
+    pub fn new(registers: &[u32]) -> Outcome {
+        assert!(
+            registers.len() <= 4,
+            "litmus tests have at most four registers"
+        );
+        let mut outcome = Outcome {
+            registers: [0; 4],
+            len: registers.len(),
+        };
+        outcome.registers[..registers.len()].copy_from_slice(registers);
+        outcome
+    }
+
+    /// The register values
+    pub fn registers(&self) -> &[u32] {
+        &self.registers[..self.len]
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, value) in self.registers().iter().enumerate() {
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "r{}={}", index, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shared locations and result registers of one instance of a test
+#[derive(Default)]
+struct Instance {
+    x: AtomicU32,
+    y: AtomicU32,
+    registers: [AtomicU32; 4],
+}
+
+impl Instance {
+    fn reset(&self) {
+        self.x.store(0, Ordering::Relaxed);
+        self.y.store(0, Ordering::Relaxed);
+        for register in &self.registers {
+            register.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn outcome(&self, registers: usize) -> Outcome {
+        let values: Vec<u32> = self.registers[..registers]
+            .iter()
+            .map(|register| register.load(Ordering::Relaxed))
+            .collect();
+        Outcome::new(&values)
+    }
+}
+
+/// Observed outcomes of a pattern run with one ordering
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LitmusReport {
+    pub pattern: Pattern,
+    pub ordering: Ordering,
+    pub iterations: usize,
+    /// How many times each outcome was observed
+    pub histogram: BTreeMap<Outcome, u64>,
+    pub elapsed: Duration,
+}
+
+impl LitmusReport {
+    /// Runs that ended in an outcome the memory model forbids
+    pub fn forbidden(&self) -> u64 {
+        self.histogram
+            .iter()
+            .filter(|(outcome, _)| !self.pattern.allows(self.ordering, outcome))
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+impl fmt::Display for LitmusReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} with {}: {} runs in {:.2?}",
+            self.pattern,
+            ordering_name(self.ordering),
+            self.iterations,
+            self.elapsed
+        )?;
+        if self.forbidden() > 0 {
+            write!(f, ", {} FORBIDDEN", self.forbidden())?;
+        }
+        // Every allowed outcome is listed, observed or not, then anything else seen
+        let relaxed = self.pattern.relaxed_outcome();
+        let mut outcomes: Vec<Outcome> = self.histogram.keys().copied().collect();
+        if !outcomes.contains(&relaxed) {
+            outcomes.push(relaxed);
+            outcomes.sort();
+        }
+        for outcome in outcomes {
+            let count = self.histogram.get(&outcome).copied().unwrap_or(0);
+            let verdict = if self.pattern.allows(self.ordering, &outcome) {
+                "allowed"
+            } else {
+                "forbidden"
+            };
+            write!(f, "\n  {:<23} {:>12}  {}", outcome, count, verdict)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `pattern` `iterations` times with `ordering`, `batch` instances at a time
+pub fn run(pattern: Pattern, ordering: Ordering, iterations: usize, batch: usize) -> LitmusReport {
+    assert!(batch > 0, "a batch needs one instance");
+    let instances: Vec<Instance> = (0..batch.min(iterations.max(1)))
+        .map(|_| Instance::default())
+        .collect();
+    // The runner thread resets and tallies between two waits of the workers
+    let barrier = Barrier::new(pattern.threads() + 1);
+    let done = AtomicBool::new(false);
+    let mut histogram = BTreeMap::new();
+    let started = Instant::now();
+    thread::scope(|scope| {
+        for thread in 0..pattern.threads() {
+            let (instances, barrier, done) = (&instances, &barrier, &done);
+            scope.spawn(move || {
+                loop {
+                    barrier.wait();
+                    if done.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    for instance in instances {
+                        pattern.step(thread, instance, ordering);
+                    }
+                    barrier.wait();
+                }
+            });
+        }
+        let mut remaining = iterations;
+        while remaining > 0 {
+            let count = remaining.min(instances.len());
+            for instance in &instances {
+                instance.reset();
+            }
+            barrier.wait();
+            barrier.wait();
+            for instance in &instances[..count] {
+                *histogram
+                    .entry(instance.outcome(pattern.registers()))
+                    .or_insert(0) += 1;
+            }
+            remaining -= count;
+        }
+        done.store(true, Ordering::Relaxed);
+        barrier.wait();
+    });
+    LitmusReport {
+        pattern,
+        ordering,
+        iterations,
+        histogram,
+        elapsed: started.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_outcomes_follow_the_orderings() {
+        let mp = Pattern::MessagePassing;
+        let flag_without_data = Outcome::new(&[1, 0]);
+        assert!(mp.allows(Ordering::Relaxed, &flag_without_data));
+        assert!(mp.allows(Ordering::Release, &flag_without_data));
+        assert!(!mp.allows(Ordering::AcqRel, &flag_without_data));
+        assert!(mp.allows(Ordering::AcqRel, &Outcome::new(&[0, 1])));
+
+        let both_missed = Pattern::StoreBuffering.relaxed_outcome();
+        assert!(Pattern::StoreBuffering.allows(Ordering::AcqRel, &both_missed));
+        assert!(!Pattern::StoreBuffering.allows(Ordering::SeqCst, &both_missed));
+        assert!(!Pattern::LoadBuffering.allows(Ordering::AcqRel, &Outcome::new(&[1, 1])));
+        assert!(Pattern::Iriw.allows(Ordering::AcqRel, &Outcome::new(&[1, 0, 1, 0])));
+        assert!(!Pattern::Iriw.allows(Ordering::SeqCst, &Outcome::new(&[1, 0, 1, 0])));
+    }
+
+    #[test]
+    fn runs_every_pattern_without_forbidden_outcomes() {
+        for pattern in PATTERNS {
+            for ordering in [Ordering::AcqRel, Ordering::SeqCst] {
+                let report = run(pattern, ordering, 2_500, 1_000);
+                assert_eq!(report.histogram.values().sum::<u64>(), 2_500);
+                assert_eq!(report.forbidden(), 0, "{}", report);
+                assert!(
+                    report
+                        .histogram
+                        .keys()
+                        .all(|outcome| outcome.registers().len() == pattern.registers())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn names_and_display() {
+        assert_eq!(Pattern::from_name("iriw"), Some(Pattern::Iriw));
+        assert_eq!(parse_ordering("acqrel"), Some(Ordering::AcqRel));
+        assert_eq!(parse_ordering("consume"), None);
+        assert_eq!(Outcome::new(&[1, 0]).to_string(), "r0=1 r1=0");
+        let report = run(Pattern::MessagePassing, Ordering::SeqCst, 10, 4);
+        let text = report.to_string();
+        assert!(text.starts_with("mp with seqcst: 10 runs in "));
+        // The interesting outcome is listed even when it was not observed
+        assert!(text.contains("r0=1 r1=0"));
+    }
+}
@@ -0,0 +1,323 @@
+//! Spinlocks next to `std::sync::Mutex`.
+//!
+//! Every lock here implements [`Lock`]: `lock` hands out an RAII guard that
+//! derefs to the protected value and releases the lock when dropped. The
+//! spinlocks share [`SpinLock`] and differ only in how they wait:
+//!
+//! - [`TasLock`] swaps the flag in a loop, so every waiter writes the cache
+//!   line the owner needs to release the lock.
+//! - [`TtasLock`] spins on plain loads until the lock looks free and only
+//!   then tries to take it, keeping the line shared while it waits.
+//! - [`BackoffLock`] waits like `TtasLock` but spins twice as long every time
+//!   it finds the lock still taken, and yields the thread once the spins get
+//!   long.
+//!
+//! `bench counter` runs the increment workload of the `mutex` demo with each
+//! of them.
+
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::hint;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+/// A lock protecting a value of type `T`
+pub trait Lock<T>: Send + Sync {
+    /// Releases the lock when dropped
+    type Guard<'a>: DerefMut<Target = T>
+    where
+        Self: 'a;
+
+    /// Creates an unlocked lock holding `value`
+    fn new(value: T) -> Self
+    where
+        Self: Sized;
+
+    /// Blocks until the lock is held
+    fn lock(&self) -> Self::Guard<'_>;
+}
+
+impl<T: Send> Lock<T> for Mutex<T> {
+    type Guard<'a>
+        = MutexGuard<'a, T>
+    where
+        T: 'a;
+
+    fn new(value: T) -> Self {
+        Mutex::new(value)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, T> {
+        // A panic while holding the lock cannot leave an increment half done
+        Mutex::lock(self).unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// How a [`SpinLock`] waits for the flag to clear
+pub trait Spin {
+    /// Sets `locked`, returning once this thread did
+    fn acquire(locked: &AtomicBool);
+}
+
+/// Test-and-set: swap until the swap returns `false`
+#[derive(Debug)]
+pub enum Tas {}
+
+impl Spin for Tas {
+    fn acquire(locked: &AtomicBool) {
+        while locked.swap(true, Ordering::Acquire) {
+            hint::spin_loop();
+        }
+    }
+}
+
+/// Test-and-test-and-set: read until the lock is free, then swap
+#[derive(Debug)]
+pub enum Ttas {}
+
+impl Spin for Ttas {
+    fn acquire(locked: &AtomicBool) {
+        while locked.swap(true, Ordering::Acquire) {
+            while locked.load(Ordering::Relaxed) {
+                hint::spin_loop();
+            }
+        }
+    }
+}
+
+/// Test-and-test-and-set with exponential backoff, then yielding
+#[derive(Debug)]
+pub enum Backoff {}
+
+impl Backoff {
+    /// Spins of the first wait, doubled after every failed attempt
+    const MIN_SPINS: u32 = 1;
+    /// Past this many spins per wait the thread yields instead
+    const MAX_SPINS: u32 = 64;
+}
+
+impl Spin for Backoff {
+    fn acquire(locked: &AtomicBool) {
+        let mut spins = Backoff::MIN_SPINS;
+        while locked.swap(true, Ordering::Acquire) {
+            while locked.load(Ordering::Relaxed) {
+                if spins <= Backoff::MAX_SPINS {
+                    for _ in 0..spins {
+                        hint::spin_loop();
+                    }
+                    spins *= 2;
+                } else {
+                    thread::yield_now();
+                }
+            }
+        }
+    }
+}
+
+/// A lock that busy-waits on an `AtomicBool`, the waiting done by `S`
+pub struct SpinLock<T, S> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+    spin: PhantomData<fn() -> S>,
+}
+
+/// A test-and-set spinlock
+pub type TasLock<T> = SpinLock<T, Tas>;
+
+/// A test-and-test-and-set spinlock
+pub type TtasLock<T> = SpinLock<T, Ttas>;
+
+/// A test-and-test-and-set spinlock with exponential backoff
+pub type BackoffLock<T> = SpinLock<T, Backoff>;
+
+// SAFETY: the flag hands the value to one thread at a time, like a `Mutex`
+unsafe impl<T: Send, S> Sync for SpinLock<T, S> {}
+
+impl<T, S: Spin> SpinLock<T, S> {
+    /// Creates an unlocked lock holding `value`
+    pub const fn new(value: T) -> SpinLock<T, S> {
+        SpinLock {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+            spin: PhantomData,
+        }
+    }
+
+    /// Spins until the lock is held
+    pub fn lock(&self) -> SpinGuard<'_, T, S> {
+        S::acquire(&self.locked);
+        SpinGuard { lock: self }
+    }
+
+    /// Takes the lock if it is free
+    pub fn try_lock(&self) -> Option<SpinGuard<'_, T, S>> {
+        (!self.locked.swap(true, Ordering::Acquire)).then(|| SpinGuard { lock: self })
+    }
+
+    /// Whether some guard currently holds the lock
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    /// Mutable access without locking, the borrow proves nobody else has it
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Returns the protected value
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: Default, S: Spin> Default for SpinLock<T, S> {
+    fn default() -> Self {
+        SpinLock::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug, S: Spin> fmt::Debug for SpinLock<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("SpinLock");
+        match self.try_lock() {
+            Some(guard) => debug.field("value", &*guard),
+            None => debug.field("value", &format_args!("<locked>")),
+        };
+        debug.finish()
+    }
+}
+
+impl<T: Send, S: Spin> Lock<T> for SpinLock<T, S> {
+    type Guard<'a>
+        = SpinGuard<'a, T, S>
+    where
+        Self: 'a;
+
+    fn new(value: T) -> Self {
+        SpinLock::new(value)
+    }
+
+    fn lock(&self) -> SpinGuard<'_, T, S> {
+        SpinLock::lock(self)
+    }
+}
+
+/// Holds a [`SpinLock`], releasing it on drop.
+///
+/// Like `MutexGuard`, a guard is only shared between threads if `T` can be:
+///
+/// ```compile_fail
+/// use std::cell::Cell;
+/// use std::thread;
+///
+/// use rust_concurrency::lock::TasLock;
+///
+/// let lock = TasLock::new(Cell::new(0));
+/// let guard = lock.lock();
+/// thread::scope(|scope| {
+///     scope.spawn(|| guard.set(1));
+///     guard.set(2);
+/// });
+/// ```
+pub struct SpinGuard<'a, T, S> {
+    lock: &'a SpinLock<T, S>,
+}
+
+// SAFETY: a shared guard only hands out `&T`, as `MutexGuard` does. Without
+// this impl the guard would be `Sync` whenever the lock is, that is for any
+// `T: Send`
+unsafe impl<T: Sync, S> Sync for SpinGuard<'_, T, S> {}
+
+impl<T, S> Deref for SpinGuard<'_, T, S> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the guard holds the lock
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T, S> DerefMut for SpinGuard<'_, T, S> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the guard holds the lock, and `&mut self` keeps it unique
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T, S> Drop for SpinGuard<'_, T, S> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+impl<T: fmt::Debug, S> fmt::Debug for SpinGuard<'_, T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// The `mutex` demo's workload against any lock
+    fn increment<L: Lock<i32> + 'static>(threads: usize, increments: usize) -> i32 {
+        let lock = Arc::new(L::new(0));
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let lock = Arc::clone(&lock);
+                thread::spawn(move || {
+                    for _ in 0..increments {
+                        *lock.lock() += 1;
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        *lock.lock()
+    }
+
+    #[test]
+    fn every_lock_keeps_the_count_exact() {
+        assert_eq!(increment::<Mutex<i32>>(4, 50_000), 200_000);
+        assert_eq!(increment::<TasLock<i32>>(4, 50_000), 200_000);
+        assert_eq!(increment::<TtasLock<i32>>(4, 50_000), 200_000);
+        assert_eq!(increment::<BackoffLock<i32>>(4, 50_000), 200_000);
+    }
+
+    #[test]
+    fn guards_release_on_drop() {
+        let lock = BackoffLock::new(vec![1]);
+        {
+            let mut guard = lock.lock();
+            guard.push(2);
+            assert!(lock.is_locked());
+            assert!(lock.try_lock().is_none());
+            assert_eq!(format!("{:?}", lock), "SpinLock { value: <locked> }");
+        }
+        assert!(!lock.is_locked());
+        assert_eq!(*lock.try_lock().unwrap(), [1, 2]);
+        assert_eq!(format!("{:?}", lock), "SpinLock { value: [1, 2] }");
+        assert_eq!(lock.into_inner(), [1, 2]);
+    }
+
+    #[test]
+    fn waiters_get_the_lock_once_released() {
+        let lock = Arc::new(TtasLock::new(0));
+        let mut guard = lock.lock();
+        let waiter = {
+            let lock = Arc::clone(&lock);
+            thread::spawn(move || *lock.lock())
+        };
+        thread::yield_now();
+        *guard = 1;
+        drop(guard);
+        assert_eq!(waiter.join().unwrap(), 1);
+    }
+}
@@ -0,0 +1,329 @@
+//! A fixed-size thread pool built on `std::sync::mpsc`.
+//!
+//! Jobs are sent through one channel whose receiver is shared by all workers
+//! behind a `Mutex`, the same pattern as the channel demos but with several
+//! consumers taking turns. A panicking job is caught inside its worker, so the
+//! pool keeps its full size.
+//!
+//! A pool created with [`ThreadPool::with_clock`] keeps one clock participation
+//! per worker that has work to do, so jobs sleeping on a
+//! [`VirtualClock`](crate::clock::VirtualClock) see the same timing they would
+//! on real threads.
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, mpsc};
+use std::thread::{self, JoinHandle};
+
+use crate::clock::SharedClock;
+use crate::join::{self, JoinError};
+
+/// A unit of work executed by one of the workers
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed number of worker threads executing submitted jobs in FIFO order
+pub struct ThreadPool {
+    workers: Vec<JoinHandle<()>>,
+    sender: Option<mpsc::Sender<Job>>,
+    shared: Arc<Shared>,
+}
+
+/// Bookkeeping shared between the pool handle and its workers
+struct Shared {
+    /// Jobs submitted but not finished yet
+    pending: Mutex<usize>,
+    /// Signalled whenever `pending` drops to zero
+    idle: Condvar,
+    /// Jobs that panicked since the pool was created
+    panicked: AtomicUsize,
+    /// Number of workers
+    size: usize,
+    /// Clock the jobs sleep on, if it needs to know about busy workers
+    clock: Option<SharedClock>,
+}
+
+impl ThreadPool {
+    /// Creates a pool with `size` workers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn new(size: usize) -> ThreadPool {
+        ThreadPool::build(size, None)
+    }
+
+    /// Creates a pool with `size` workers whose jobs sleep on `clock`.
+    ///
+    /// The pool registers one participant for every worker that is running or
+    /// about to pick up a job, and unregisters it when the queue runs short.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn with_clock(size: usize, clock: &SharedClock) -> ThreadPool {
+        ThreadPool::build(size, Some(Arc::clone(clock)))
+    }
+
+    fn build(size: usize, clock: Option<SharedClock>) -> ThreadPool {
+        assert!(size > 0, "a thread pool needs at least one worker");
+        join::install_panic_hook();
+        let (sender, receiver) = mpsc::channel::<Job>();
+        // Every worker takes its next job from the same receiver
+        let receiver = Arc::new(Mutex::new(receiver));
+        let shared = Arc::new(Shared {
+            pending: Mutex::new(0),
+            idle: Condvar::new(),
+            panicked: AtomicUsize::new(0),
+            size,
+            clock,
+        });
+        let workers = (0..size)
+            .map(|id| {
+                let receiver = Arc::clone(&receiver);
+                let shared = Arc::clone(&shared);
+                thread::Builder::new()
+                    .name(format!("pool-worker-{}", id))
+                    .spawn(move || worker_loop(&receiver, &shared))
+                    .expect("failed to spawn a pool worker")
+            })
+            .collect();
+        ThreadPool {
+            workers,
+            sender: Some(sender),
+            shared,
+        }
+    }
+
+    /// Number of worker threads
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Number of jobs that panicked so far
+    pub fn panicked_jobs(&self) -> usize {
+        self.shared.panicked.load(Ordering::Relaxed)
+    }
+
+    /// Queues a job without a way to observe its result
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut pending = self.shared.pending.lock().unwrap();
+        // One more worker becomes busy while there are fewer jobs than workers
+        if let Some(clock) = &self.shared.clock
+            && *pending < self.shared.size
+        {
+            clock.enter();
+        }
+        *pending += 1;
+        drop(pending);
+        self.sender
+            .as_ref()
+            .expect("the pool is running until it is dropped")
+            .send(Box::new(job))
+            .expect("workers live as long as the pool");
+    }
+
+    /// Queues a job and returns a handle to wait for its result
+    pub fn submit<F, T>(&self, job: F) -> TaskHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (result_sender, result_receiver) = mpsc::sync_channel(1);
+        let shared = Arc::clone(&self.shared);
+        self.execute(move || {
+            let result = join::catch_panic(job);
+            // The payload goes to the handle, the worker never sees the panic
+            if result.is_err() {
+                shared.panicked.fetch_add(1, Ordering::Relaxed);
+            }
+            // The caller may have dropped the handle, nobody is waiting then
+            let _ = result_sender.send(result);
+        });
+        TaskHandle {
+            receiver: result_receiver,
+        }
+    }
+
+    /// Blocks until every job submitted so far has finished.
+    ///
+    /// The pool stays usable afterwards.
+    pub fn join(&self) {
+        let mut pending = self.shared.pending.lock().unwrap();
+        while *pending > 0 {
+            pending = self.shared.idle.wait(pending).unwrap();
+        }
+    }
+
+    /// Stops accepting jobs, lets the workers drain the queue and joins them
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    /// Closes the job channel and joins every worker
+    fn stop(&mut self) {
+        // Dropping the sender makes `recv` fail once the queue is empty
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            // Jobs cannot unwind through the worker loop, so this only fails
+            // if the worker itself is broken
+            worker
+                .join()
+                .expect("pool worker panicked outside of a job");
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl fmt::Debug for ThreadPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThreadPool")
+            .field("size", &self.size())
+            .field("panicked_jobs", &self.panicked_jobs())
+            .finish()
+    }
+}
+
+/// Body of every worker: run jobs until the channel is closed and drained
+fn worker_loop(receiver: &Mutex<mpsc::Receiver<Job>>, shared: &Shared) {
+    loop {
+        // The lock is released at the end of this statement, before the job runs
+        let job = receiver.lock().unwrap().recv();
+        let Ok(job) = job else {
+            break;
+        };
+        if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+            shared.panicked.fetch_add(1, Ordering::Relaxed);
+        }
+        let mut pending = shared.pending.lock().unwrap();
+        *pending -= 1;
+        // Keep this worker registered as long as the queue can keep it busy
+        if let Some(clock) = &shared.clock
+            && *pending < shared.size
+        {
+            clock.leave();
+        }
+        if *pending == 0 {
+            shared.idle.notify_all();
+        }
+    }
+}
+
+/// Result of a job queued with [`ThreadPool::submit`]
+#[derive(Debug)]
+pub struct TaskHandle<T> {
+    receiver: mpsc::Receiver<Result<T, JoinError>>,
+}
+
+impl<T> TaskHandle<T> {
+    /// Blocks until the task has run and returns its value
+    pub fn join(self) -> Result<T, JoinError> {
+        self.receiver
+            .recv()
+            .expect("the pool runs every queued task before shutting down")
+    }
+
+    /// Returns the result if the task has already finished
+    pub fn try_join(&self) -> Option<Result<T, JoinError>> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn submit_returns_results() {
+        let pool = ThreadPool::new(3);
+        let handles: Vec<_> = (0..10).map(|i| pool.submit(move || i * i)).collect();
+        let results: Vec<i32> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, (0..10).map(|i| i * i).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn jobs_run_on_a_fixed_set_of_threads() {
+        let pool = ThreadPool::new(2);
+        let names = Arc::new(Mutex::new(Vec::new()));
+        for _ in 0..20 {
+            let names = Arc::clone(&names);
+            pool.execute(move || {
+                let name = thread::current().name().unwrap().to_string();
+                names.lock().unwrap().push(name);
+            });
+        }
+        pool.join();
+        let mut names = names.lock().unwrap().clone();
+        assert_eq!(names.len(), 20);
+        names.sort();
+        names.dedup();
+        assert!(names.len() <= 2);
+        assert!(names.iter().all(|name| name.starts_with("pool-worker-")));
+    }
+
+    #[test]
+    fn panicking_job_does_not_kill_the_pool() {
+        let pool = ThreadPool::new(1);
+        let failed = pool.submit(|| -> i32 { panic!("boom") });
+        let error = failed.join().unwrap_err();
+        assert_eq!(error.message(), Some("boom"));
+        assert_eq!(error.thread_name(), Some("pool-worker-0"));
+        assert!(error.location().is_some());
+        // Counted before the handle gets the error
+        assert_eq!(pool.panicked_jobs(), 1);
+        pool.execute(|| panic!("again"));
+        // The single worker survived both panics
+        assert_eq!(pool.submit(|| 42).join().unwrap(), 42);
+        assert_eq!(pool.panicked_jobs(), 2);
+    }
+
+    #[test]
+    fn pool_keeps_virtual_time_in_step() {
+        use crate::clock::{self, VirtualClock};
+
+        let clock = VirtualClock::shared();
+        let pool = ThreadPool::with_clock(3, &clock);
+        // Hold the clock still until every job has been queued
+        let setup = clock::participate(&clock);
+        let handles: Vec<_> = (0..7)
+            .map(|_| {
+                let clock = Arc::clone(&clock);
+                pool.submit(move || {
+                    clock.sleep(Duration::from_secs(1));
+                    clock.now()
+                })
+            })
+            .collect();
+        drop(setup);
+        let finished: Vec<u64> = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap().as_secs())
+            .collect();
+        // Seven one second jobs on three workers finish in three waves
+        assert_eq!(finished, vec![1, 1, 1, 2, 2, 2, 3]);
+    }
+
+    #[test]
+    fn shutdown_drains_the_queue() {
+        let pool = ThreadPool::new(2);
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..8 {
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                thread::sleep(Duration::from_millis(5));
+                done.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        pool.shutdown();
+        assert_eq!(done.load(Ordering::Relaxed), 8);
+    }
+}